@@ -0,0 +1,101 @@
+// This file contains the code for converting the values of selected
+// HTML attributes, e.g. `title` and `alt`.
+//
+// Most attributes must never be touched (imagine curling the quotes in
+// an `href`), so this is opt-in: only the attributes named in the config
+// are converted, and everything else in the tag is left byte-for-byte
+// as it was.
+
+use regex::{Captures, Regex};
+
+use crate::config::SubstitutionConfig;
+use crate::context::QuoteContext;
+use crate::converters;
+use crate::entities::*;
+use crate::locale::Locale;
+use crate::nesting::QuoteStack;
+
+lazy_static! {
+    // An opening tag, e.g. <a href="…">.  This deliberately doesn't
+    // match closing tags, comments, doctypes or processing instructions.
+    static ref OPENING_TAG_RE: Regex = Regex::new(r"^<[A-Za-z]").unwrap();
+
+    // An attribute with a value.  The value may be wrapped in double
+    // quotes, single quotes, or nothing at all.
+    static ref ATTRIBUTE_RE: Regex = Regex::new(r#"(?x)
+        (?P<name>[^\s"'<>/=]+)
+        (?P<equals>\s*=\s*)
+        (?:
+            "(?P<double>[^"]*)"
+            |
+            '(?P<single>[^']*)'
+            |
+            (?P<unquoted>[^\s"'=<>`][^\s"=<>`]*)
+        )
+    "#).unwrap();
+
+    // A <meta> tag whose `content` is prose that gets shown to people,
+    // rather than e.g. a URL or a viewport setting.
+    static ref DESCRIPTIVE_META_RE: Regex = Regex::new(r#"(?xis)
+        ^<meta\s
+        .*
+        \b(?:name|property)\s*=\s*["']?
+        (?:description|og:description|og:title|twitter:description|twitter:title)
+        (?:["'\s/>])
+    "#).unwrap();
+}
+
+/// Convert the values of any attributes in `tag` that are named in
+/// `config.smartened_attributes`.
+pub fn smarten_attributes(tag: &str, config: &SubstitutionConfig, locale: &Locale) -> String {
+    if !(*OPENING_TAG_RE).is_match(tag) {
+        return tag.to_string();
+    }
+
+    let is_descriptive_meta = (*DESCRIPTIVE_META_RE).is_match(tag);
+
+    let result = (*ATTRIBUTE_RE).replace_all(tag, |cap: &Captures| {
+        let name = cap["name"].to_lowercase();
+
+        let is_smartened = config.smartened_attributes
+            .iter()
+            .any(|attr| attr.to_lowercase() == name);
+
+        if !is_smartened || (name == "content" && !is_descriptive_meta) {
+            return cap[0].to_string();
+        }
+
+        let equals = &cap["equals"];
+
+        // Work out how the value was quoted, because we need to escape
+        // any instances of that delimiter that we put back in the value.
+        //
+        // Unquoted values can't contain spaces or quotes, so if we're
+        // converting one we wrap it in double quotes to be safe.
+        if let Some(value) = cap.name("single") {
+            let value = smarten_value(value.as_str(), config, locale)
+                .replace('\'', SINGLE_STRAIGHT_QUOTE_ENTITY);
+            format!("{}{}'{}'", &cap["name"], equals, value)
+        } else {
+            let value = cap.name("double").or_else(|| cap.name("unquoted")).unwrap();
+            let value = smarten_value(value.as_str(), config, locale)
+                .replace('"', DOUBLE_STRAIGHT_QUOTE_ENTITY);
+            format!("{}{}\"{}\"", &cap["name"], equals, value)
+        }
+    });
+
+    result.to_string()
+}
+
+/// Convert a single attribute value.
+///
+/// Quotes inside an attribute value are usually written as entities
+/// (e.g. `title="He said &quot;hi&quot;"`), so we turn those back into
+/// straight quotes before converting.
+fn smarten_value(value: &str, config: &SubstitutionConfig, locale: &Locale) -> String {
+    let value = converters::decode_quote_entities(value);
+
+    // Each attribute value gets its own quote stack, because quotations
+    // can't start in the text and finish in an attribute (or vice versa).
+    crate::convert_text(&value, config, locale, &QuoteContext::default(), &mut QuoteStack::new(config, false), false)
+}
@@ -1,3 +1,5 @@
+use crate::locale::Locale;
+
 #[derive(Debug)]
 pub enum DashesSubstitution {
     /// Leave dashes as-is
@@ -15,8 +17,66 @@ pub enum EllipsesSubstitution {
     /// Leave ellipses as-is
     DoNothing,
 
-    /// Convert ellipses to their HTML entities
+    /// Convert ellipses to their HTML entities.  This is the same as
+    /// `Custom(EllipsesOptions::default())`.
     ConvertToEntity,
+
+    /// Convert ellipses with the given options
+    Custom(EllipsesOptions),
+}
+
+#[derive(Debug)]
+pub struct EllipsesOptions {
+    /// How to write the ellipsis
+    pub style: EllipsisStyle,
+
+    /// What to do with four dots, e.g. an ellipsis at the end of a sentence
+    pub four_dots: FourDotEllipsis,
+
+    /// What to do with a space before the ellipsis
+    pub space_before: EllipsisSpaceBinding,
+}
+
+impl Default for EllipsesOptions {
+    fn default() -> Self {
+        EllipsesOptions {
+            style: EllipsisStyle::Character,
+            four_dots: FourDotEllipsis::EllipsisThenPeriod,
+            space_before: EllipsisSpaceBinding::DoNothing,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EllipsisStyle {
+    /// A single ellipsis character (`…`)
+    Character,
+
+    /// Three periods with no-break spaces between them, as recommended
+    /// by the Chicago Manual of Style (`.&nbsp;.&nbsp;.`)
+    SpacedPeriods,
+}
+
+#[derive(Debug)]
+pub enum FourDotEllipsis {
+    /// An ellipsis, then the extra period (`….`)
+    EllipsisThenPeriod,
+
+    /// The period that ends the sentence, then an ellipsis (`.…`)
+    PeriodThenEllipsis,
+
+    /// Drop the extra period, and just use an ellipsis (`…`)
+    Ellipsis,
+}
+
+#[derive(Debug)]
+pub enum EllipsisSpaceBinding {
+    /// Leave the space before an ellipsis as-is
+    DoNothing,
+
+    /// Replace a space before an ellipsis with a no-break space, so the
+    /// ellipsis can't be wrapped onto the start of a line
+    NoBreakSpace,
 }
 
 #[derive(Debug)]
@@ -43,6 +103,162 @@ pub enum EntitiesSubstitution {
     AsciiEquivalents,
 }
 
+#[derive(Debug)]
+pub enum SpacingSubstitution {
+    /// Leave the spacing around punctuation as-is
+    DoNothing,
+
+    /// Follow the conventions of the locale, e.g. use French spacing
+    /// for French text
+    FromLocale,
+
+    /// Always use French spacing: a narrow no-break space before `;`,
+    /// `:`, `!`, `?` and `»`, and after `«`
+    French,
+}
+
+#[derive(Debug)]
+pub enum NormalizationSubstitution {
+    /// Leave existing curly quotes, dashes and ellipses as-is
+    DoNothing,
+
+    /// Re-render existing curly quotes, dashes and ellipses (in any
+    /// encoding) in the configured style, as if they'd been typed as
+    /// straight quotes, `--`/`---` and `...`
+    Normalize,
+}
+
+#[derive(Debug)]
+pub enum DashSpacing {
+    /// Leave the spacing as the author typed it
+    DoNothing,
+
+    /// No space either side, e.g. `word—word`
+    Closed,
+
+    /// A normal space either side, e.g. `word — word`
+    Spaced,
+
+    /// A hair space (U+200A) either side
+    HairSpaces,
+
+    /// A narrow no-break space (U+202F) either side
+    ThinNoBreakSpaces,
+}
+
+#[derive(Debug)]
+pub enum SymbolsSubstitution {
+    /// Leave the text as-is
+    DoNothing,
+
+    /// Convert to the symbol, e.g. `−`, `×` or `©`
+    ConvertToSymbol,
+}
+
+/// A rule for where to put a no-break space, so a line doesn't break
+/// between two things that belong together.
+#[derive(Debug)]
+pub enum NoBreakSpaceRule {
+    /// A number and its unit, e.g. `10 kg`
+    NumberAndUnit,
+
+    /// An abbreviation and the number it refers to, e.g. `p. 42`
+    AbbreviationAndNumber,
+
+    /// An honorific and a name, e.g. `Mr. Smith`
+    HonorificAndName,
+
+    /// A one-letter preposition or conjunction and the next word, which
+    /// mustn't be left at the end of a line in Czech, Polish and Slovak,
+    /// e.g. `v Praze`.  This only applies to text in those languages.
+    ShortWords,
+
+    /// The space before an ellipsis, e.g. `Well …`
+    BeforeEllipsis,
+
+    /// An em dash and the word after it, e.g. `— and`
+    AfterEmDash,
+}
+
+#[derive(Debug)]
+pub enum OrdinalsSubstitution {
+    /// Leave ordinal numbers as-is
+    DoNothing,
+
+    /// Raise the suffix of an ordinal number, e.g. `1st` to `1<sup>st</sup>`
+    /// in HTML, or `1ˢᵗ` in plain text.  The suffixes depend on the locale.
+    Superscript,
+}
+
+#[derive(Debug)]
+pub enum PrimesSubstitution {
+    /// Treat quotes after digits like any other quotes
+    DoNothing,
+
+    /// Convert quotes after digits in measurements and coordinates, e.g.
+    /// `5'10"` or `40°26'46"N`, into primes (′ and ″)
+    ConvertToPrimes,
+}
+
+#[derive(Debug)]
+pub enum QuoteNestingSubstitution {
+    /// Decide which way each quote curls on its own, and use primary
+    /// quotes for `"` and secondary quotes for `'`
+    DoNothing,
+
+    /// Keep track of which quotes are open, use that to fix quotes whose
+    /// direction is ambiguous, and alternate between primary and secondary
+    /// quotes by how deeply they're nested
+    TrackNesting,
+}
+
+#[derive(Debug)]
+pub enum QElementsSubstitution {
+    /// Leave `<q>` elements as-is
+    DoNothing,
+
+    /// Remove straight quotes typed just inside a `<q>` element, because
+    /// the browser adds its own quotes
+    StripInnerQuotes,
+
+    /// Replace `<q>` and `</q>` with curly quotes for the locale and
+    /// how deeply the quotation is nested, and remove any straight quotes
+    /// typed just inside them
+    ReplaceWithQuotes,
+}
+
+#[derive(Debug)]
+pub enum ApostropheSubstitution {
+    /// Use a right single quotation mark (’), the same as a closing quote
+    RightSingleQuote,
+
+    /// Use a modifier letter apostrophe (ʼ), e.g. for Ukrainian or
+    /// for transliterations
+    ModifierLetterApostrophe,
+
+    /// Use a modifier letter turned comma (ʻ), e.g. for the Hawaiian ʻokina
+    ModifierLetterTurnedComma,
+}
+
+#[derive(Debug)]
+pub enum GuillemetsSubstitution {
+    /// Leave `<<` and `>>` as-is
+    DoNothing,
+
+    /// Convert `<<` and `>>` to guillemets (« and »)
+    ConvertToGuillemets,
+}
+
+#[derive(Debug)]
+pub enum InputFormat {
+    /// Treat the input as HTML, and leave tags untouched
+    Html,
+
+    /// Treat the input as plain text, where `<` and `>` are literal
+    /// characters and blank lines separate paragraphs
+    PlainText,
+}
+
 pub struct SubstitutionConfig {
     /// Whether to convert double dashes (`--`) to en/em dashes
     pub double_dash: DashesSubstitution,
@@ -50,6 +266,71 @@ pub struct SubstitutionConfig {
     /// Whether to convert triple dashes (`---`) to en/em dashes
     pub triple_dash: DashesSubstitution,
 
+    /// Whether to convert a hyphen with a space either side (` - `) to
+    /// en/em dashes.  A hyphen at the start of a line is left as-is,
+    /// because it's probably a list marker.
+    pub single_spaced_hyphen: DashesSubstitution,
+
+    /// How to space em dashes, including any the author typed
+    pub em_dash_spacing: DashSpacing,
+
+    /// How to space en dashes that are used as punctuation, including any
+    /// the author typed.  En dashes that are already closed up, e.g. in
+    /// `1912–2004`, are left as-is.
+    pub en_dash_spacing: DashSpacing,
+
+    /// Whether to convert hyphens between numbers (e.g. `1912-2004`) to
+    /// en/em dashes.  Dates, phone numbers and identifiers are left as-is.
+    pub number_ranges: DashesSubstitution,
+
+    /// Whether to convert a hyphen before a number (e.g. `-5`) to a
+    /// minus sign
+    pub minus_sign: SymbolsSubstitution,
+
+    /// Whether to convert an `x` between numbers (e.g. `3x4` or
+    /// `1920 x 1080`) to a multiplication sign
+    pub multiplication_sign: SymbolsSubstitution,
+
+    /// Whether to convert `+-` and `+/-` to a plus-minus sign
+    pub plus_minus_sign: SymbolsSubstitution,
+
+    /// Whether to convert `(c)` to a copyright symbol (`©`).  A `(c)`
+    /// straight after `(b)` is a list item, so it's left as-is.
+    pub copyright_symbol: SymbolsSubstitution,
+
+    /// Whether to convert `(r)` to a registered trademark symbol (`®`)
+    pub registered_symbol: SymbolsSubstitution,
+
+    /// Whether to convert `(tm)` to a trademark symbol (`™`)
+    pub trademark_symbol: SymbolsSubstitution,
+
+    /// Whether to convert `(p)` to a sound recording copyright symbol (`℗`)
+    pub sound_recording_symbol: SymbolsSubstitution,
+
+    /// Whether to convert `c/o` to a care-of symbol (`℅`)
+    pub care_of_symbol: SymbolsSubstitution,
+
+    /// Whether to convert arrows (`->`, `<-`, `<->`, `=>` and `<=>`) to
+    /// arrow symbols.  A long arrow like `-->` is an arrow, not a dash.
+    pub arrows: SymbolsSubstitution,
+
+    /// Whether to convert comparison operators (`<=`, `>=` and `!=`) to their
+    /// mathematical symbols
+    pub comparison_operators: SymbolsSubstitution,
+
+    /// Whether to convert fractions (e.g. `1/2` or `3/16`) to vulgar
+    /// fractions.  Dates and URLs are left as-is, but a day and month
+    /// without a year (e.g. `9/11`) looks like a fraction, so it's converted.
+    pub fractions: SymbolsSubstitution,
+
+    /// Whether to raise the suffixes of ordinal numbers, e.g. `1st`
+    pub ordinals: OrdinalsSubstitution,
+
+    /// Where to replace spaces with no-break spaces, e.g. between a number
+    /// and its unit.  The no-break spaces are written according to
+    /// `entities`.
+    pub no_break_spaces: Vec<NoBreakSpaceRule>,
+
     /// Whether to convert ellipses (`...`) into ellipsis HTML entities
     pub ellipses: EllipsesSubstitution,
 
@@ -62,8 +343,57 @@ pub struct SubstitutionConfig {
     /// Whether to convert normal quotes (`"` and `'`) to curly quotes
     pub quote_chars: QuotesSubstitution,
 
+    /// Whether to convert HTML-escaped quotes (e.g. `&quot;` and `&#39;`)
+    /// to curly quotes, as if they were normal quotes
+    pub escaped_quotes: QuotesSubstitution,
+
+    /// Whether to convert quotes after digits into primes, e.g. for feet
+    /// and inches
+    pub primes: PrimesSubstitution,
+
+    /// Whether to keep track of nested quotes, and pick primary/secondary
+    /// quotes based on how deeply they're nested
+    pub quote_nesting: QuoteNestingSubstitution,
+
+    /// What to do with `<q>` elements
+    pub q_elements: QElementsSubstitution,
+
+    /// Which glyph to use for apostrophes, e.g. in "don't" or "'tis"
+    pub apostrophe: ApostropheSubstitution,
+
+    /// Whether to convert ASCII guillemets (`<<` and `>>`) to « and »
+    pub ascii_guillemets: GuillemetsSubstitution,
+
     /// What to convert
     pub entities: EntitiesSubstitution,
+
+    /// The language of the text, which decides e.g. which glyphs are
+    /// used for curly quotes
+    pub locale: Locale,
+
+    /// Whether to adjust the spacing around punctuation, e.g. the spaces
+    /// before `!` and `?` in French
+    pub punctuation_spacing: SpacingSubstitution,
+
+    /// Whether to re-render curly quotes, dashes and ellipses that were
+    /// already in the input
+    pub normalization: NormalizationSubstitution,
+
+    /// Extra words with an apostrophe at the start or the end, which
+    /// should be treated as an apostrophe rather than a quote, e.g.
+    /// `'gator` or `o'`.  These are used alongside the built-in list for
+    /// the locale.
+    pub elisions: Vec<String>,
+
+    /// Whether the input is HTML or plain text
+    pub input_format: InputFormat,
+
+    /// Names of HTML attributes whose values should be converted, e.g.
+    /// `title` or `alt`.  Attribute values are left as-is by default.
+    ///
+    /// The `content` attribute is only converted on `<meta>` tags that
+    /// describe the page, e.g. `<meta name="description" content="…">`.
+    pub smartened_attributes: Vec<String>,
 }
 
 impl Default for SubstitutionConfig {
@@ -71,11 +401,40 @@ impl Default for SubstitutionConfig {
         SubstitutionConfig {
             double_dash: DashesSubstitution::EnDash,
             triple_dash: DashesSubstitution::EmDash,
+            single_spaced_hyphen: DashesSubstitution::DoNothing,
+            em_dash_spacing: DashSpacing::DoNothing,
+            en_dash_spacing: DashSpacing::DoNothing,
+            number_ranges: DashesSubstitution::DoNothing,
+            minus_sign: SymbolsSubstitution::DoNothing,
+            multiplication_sign: SymbolsSubstitution::DoNothing,
+            plus_minus_sign: SymbolsSubstitution::DoNothing,
+            copyright_symbol: SymbolsSubstitution::DoNothing,
+            registered_symbol: SymbolsSubstitution::DoNothing,
+            trademark_symbol: SymbolsSubstitution::DoNothing,
+            sound_recording_symbol: SymbolsSubstitution::DoNothing,
+            care_of_symbol: SymbolsSubstitution::DoNothing,
+            arrows: SymbolsSubstitution::DoNothing,
+            comparison_operators: SymbolsSubstitution::DoNothing,
+            fractions: SymbolsSubstitution::DoNothing,
+            ordinals: OrdinalsSubstitution::DoNothing,
+            no_break_spaces: vec![],
             ellipses: EllipsesSubstitution::ConvertToEntity,
             single_backticks: QuotesSubstitution::DoNothing,
             double_backticks: QuotesSubstitution::ConvertToCurly,
             quote_chars: QuotesSubstitution::ConvertToCurly,
+            escaped_quotes: QuotesSubstitution::DoNothing,
+            primes: PrimesSubstitution::DoNothing,
+            quote_nesting: QuoteNestingSubstitution::DoNothing,
+            q_elements: QElementsSubstitution::DoNothing,
+            apostrophe: ApostropheSubstitution::RightSingleQuote,
+            ascii_guillemets: GuillemetsSubstitution::DoNothing,
             entities: EntitiesSubstitution::HtmlNumericEntities,
+            locale: Locale::default(),
+            punctuation_spacing: SpacingSubstitution::FromLocale,
+            normalization: NormalizationSubstitution::DoNothing,
+            elisions: vec![],
+            input_format: InputFormat::Html,
+            smartened_attributes: vec![],
         }
     }
 }
@@ -83,11 +442,40 @@ impl Default for SubstitutionConfig {
 pub trait SubstitutionConfigHelpers {
     fn with_double_dash(self, substitution: DashesSubstitution) -> Self;
     fn with_triple_dash(self, substitution: DashesSubstitution) -> Self;
+    fn with_single_spaced_hyphen(self, substitution: DashesSubstitution) -> Self;
+    fn with_em_dash_spacing(self, spacing: DashSpacing) -> Self;
+    fn with_en_dash_spacing(self, spacing: DashSpacing) -> Self;
+    fn with_number_ranges(self, substitution: DashesSubstitution) -> Self;
+    fn with_minus_sign(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_multiplication_sign(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_plus_minus_sign(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_copyright_symbol(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_registered_symbol(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_trademark_symbol(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_sound_recording_symbol(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_care_of_symbol(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_arrows(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_comparison_operators(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_fractions(self, substitution: SymbolsSubstitution) -> Self;
+    fn with_ordinals(self, substitution: OrdinalsSubstitution) -> Self;
+    fn with_no_break_spaces(self, rules: Vec<NoBreakSpaceRule>) -> Self;
     fn with_ellipses(self, substitution: EllipsesSubstitution) -> Self;
     fn with_single_backticks(self, substitution: QuotesSubstitution) -> Self;
     fn with_double_backticks(self, substitution: QuotesSubstitution) -> Self;
     fn with_quote_chars(self, substitution: QuotesSubstitution) -> Self;
+    fn with_escaped_quotes(self, substitution: QuotesSubstitution) -> Self;
+    fn with_primes(self, substitution: PrimesSubstitution) -> Self;
+    fn with_quote_nesting(self, substitution: QuoteNestingSubstitution) -> Self;
+    fn with_q_elements(self, substitution: QElementsSubstitution) -> Self;
+    fn with_apostrophe(self, substitution: ApostropheSubstitution) -> Self;
+    fn with_ascii_guillemets(self, substitution: GuillemetsSubstitution) -> Self;
     fn with_entities(self, substitution: EntitiesSubstitution) -> Self;
+    fn with_locale(self, locale: Locale) -> Self;
+    fn with_punctuation_spacing(self, substitution: SpacingSubstitution) -> Self;
+    fn with_normalization(self, substitution: NormalizationSubstitution) -> Self;
+    fn with_elisions(self, elisions: Vec<String>) -> Self;
+    fn with_input_format(self, format: InputFormat) -> Self;
+    fn with_smartened_attributes(self, attributes: Vec<String>) -> Self;
 }
 
 impl SubstitutionConfigHelpers for SubstitutionConfig {
@@ -105,6 +493,125 @@ impl SubstitutionConfigHelpers for SubstitutionConfig {
         }
     }
 
+    fn with_single_spaced_hyphen(self, substitution: DashesSubstitution) -> Self {
+        SubstitutionConfig {
+            single_spaced_hyphen: substitution,
+            ..self
+        }
+    }
+
+    fn with_em_dash_spacing(self, spacing: DashSpacing) -> Self {
+        SubstitutionConfig {
+            em_dash_spacing: spacing,
+            ..self
+        }
+    }
+
+    fn with_en_dash_spacing(self, spacing: DashSpacing) -> Self {
+        SubstitutionConfig {
+            en_dash_spacing: spacing,
+            ..self
+        }
+    }
+
+    fn with_number_ranges(self, substitution: DashesSubstitution) -> Self {
+        SubstitutionConfig {
+            number_ranges: substitution,
+            ..self
+        }
+    }
+
+    fn with_minus_sign(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            minus_sign: substitution,
+            ..self
+        }
+    }
+
+    fn with_multiplication_sign(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            multiplication_sign: substitution,
+            ..self
+        }
+    }
+
+    fn with_plus_minus_sign(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            plus_minus_sign: substitution,
+            ..self
+        }
+    }
+
+    fn with_copyright_symbol(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            copyright_symbol: substitution,
+            ..self
+        }
+    }
+
+    fn with_registered_symbol(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            registered_symbol: substitution,
+            ..self
+        }
+    }
+
+    fn with_trademark_symbol(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            trademark_symbol: substitution,
+            ..self
+        }
+    }
+
+    fn with_sound_recording_symbol(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            sound_recording_symbol: substitution,
+            ..self
+        }
+    }
+
+    fn with_care_of_symbol(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            care_of_symbol: substitution,
+            ..self
+        }
+    }
+
+    fn with_arrows(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            arrows: substitution,
+            ..self
+        }
+    }
+
+    fn with_comparison_operators(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            comparison_operators: substitution,
+            ..self
+        }
+    }
+
+    fn with_fractions(self, substitution: SymbolsSubstitution) -> Self {
+        SubstitutionConfig {
+            fractions: substitution,
+            ..self
+        }
+    }
+
+    fn with_ordinals(self, substitution: OrdinalsSubstitution) -> Self {
+        SubstitutionConfig {
+            ordinals: substitution,
+            ..self
+        }
+    }
+
+    fn with_no_break_spaces(self, rules: Vec<NoBreakSpaceRule>) -> Self {
+        SubstitutionConfig {
+            no_break_spaces: rules,
+            ..self
+        }
+    }
+
     fn with_ellipses(self, substitution: EllipsesSubstitution) -> Self {
         SubstitutionConfig {
             ellipses: substitution,
@@ -133,10 +640,94 @@ impl SubstitutionConfigHelpers for SubstitutionConfig {
         }
     }
 
+    fn with_escaped_quotes(self, substitution: QuotesSubstitution) -> Self {
+        SubstitutionConfig {
+            escaped_quotes: substitution,
+            ..self
+        }
+    }
+
+    fn with_primes(self, substitution: PrimesSubstitution) -> Self {
+        SubstitutionConfig {
+            primes: substitution,
+            ..self
+        }
+    }
+
+    fn with_quote_nesting(self, substitution: QuoteNestingSubstitution) -> Self {
+        SubstitutionConfig {
+            quote_nesting: substitution,
+            ..self
+        }
+    }
+
+    fn with_q_elements(self, substitution: QElementsSubstitution) -> Self {
+        SubstitutionConfig {
+            q_elements: substitution,
+            ..self
+        }
+    }
+
+    fn with_apostrophe(self, substitution: ApostropheSubstitution) -> Self {
+        SubstitutionConfig {
+            apostrophe: substitution,
+            ..self
+        }
+    }
+
+    fn with_ascii_guillemets(self, substitution: GuillemetsSubstitution) -> Self {
+        SubstitutionConfig {
+            ascii_guillemets: substitution,
+            ..self
+        }
+    }
+
     fn with_entities(self, substitution: EntitiesSubstitution) -> Self {
         SubstitutionConfig {
             entities: substitution,
             ..self
         }
     }
+
+    fn with_locale(self, locale: Locale) -> Self {
+        SubstitutionConfig {
+            locale,
+            ..self
+        }
+    }
+
+    fn with_punctuation_spacing(self, substitution: SpacingSubstitution) -> Self {
+        SubstitutionConfig {
+            punctuation_spacing: substitution,
+            ..self
+        }
+    }
+
+    fn with_normalization(self, substitution: NormalizationSubstitution) -> Self {
+        SubstitutionConfig {
+            normalization: substitution,
+            ..self
+        }
+    }
+
+    fn with_elisions(self, elisions: Vec<String>) -> Self {
+        SubstitutionConfig {
+            elisions,
+            ..self
+        }
+    }
+
+    fn with_input_format(self, format: InputFormat) -> Self {
+        SubstitutionConfig {
+            input_format: format,
+            ..self
+        }
+    }
+
+    fn with_smartened_attributes(self, attributes: Vec<String>) -> Self {
+        SubstitutionConfig {
+            smartened_attributes: attributes,
+            ..self
+        }
+    }
 }
@@ -0,0 +1,266 @@
+// This file contains the code for looking at the characters around a
+// quote, which we use to decide whether it's an opening or closing quote.
+//
+// The same character can reach us in several spellings: a non-breaking
+// space might be a literal U+00A0, `&nbsp;`, `&#160;` or `&#xA0;`.  Rather
+// than trying to list every spelling in the quote regexes, we decode the
+// entity (if any) and then ask questions about the character it stands for.
+
+use regex::Regex;
+
+use crate::entities::{OPENING_PRIMARY_QUOTE, OPENING_SECONDARY_QUOTE};
+
+/// Named HTML entities for the characters that affect quote context.
+///
+/// This isn't the complete list of HTML entities -- it's the whitespace,
+/// dashes, quotes, brackets and a few other marks, which are the only
+/// ones we care about.
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("Tab", '\t'),
+    ("NewLine", '\n'),
+    ("nbsp", '\u{00A0}'),
+    ("NonBreakingSpace", '\u{00A0}'),
+    ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'),
+    ("emsp13", '\u{2004}'),
+    ("emsp14", '\u{2005}'),
+    ("numsp", '\u{2007}'),
+    ("puncsp", '\u{2008}'),
+    ("thinsp", '\u{2009}'),
+    ("ThinSpace", '\u{2009}'),
+    ("hairsp", '\u{200A}'),
+    ("VeryThinSpace", '\u{200A}'),
+    ("MediumSpace", '\u{205F}'),
+
+    ("hyphen", '\u{2010}'),
+    ("dash", '\u{2010}'),
+    ("ndash", '\u{2013}'),
+    ("mdash", '\u{2014}'),
+    ("horbar", '\u{2015}'),
+    ("minus", '\u{2212}'),
+
+    ("quot", '"'),
+    ("QUOT", '"'),
+    ("apos", '\''),
+    ("lsquo", '\u{2018}'),
+    ("OpenCurlyQuote", '\u{2018}'),
+    ("rsquo", '\u{2019}'),
+    ("rsquor", '\u{2019}'),
+    ("CloseCurlyQuote", '\u{2019}'),
+    ("sbquo", '\u{201A}'),
+    ("lsquor", '\u{201A}'),
+    ("ldquo", '\u{201C}'),
+    ("OpenCurlyDoubleQuote", '\u{201C}'),
+    ("rdquo", '\u{201D}'),
+    ("rdquor", '\u{201D}'),
+    ("CloseCurlyDoubleQuote", '\u{201D}'),
+    ("bdquo", '\u{201E}'),
+    ("ldquor", '\u{201E}'),
+    ("laquo", '\u{00AB}'),
+    ("raquo", '\u{00BB}'),
+    ("lsaquo", '\u{2039}'),
+    ("rsaquo", '\u{203A}'),
+
+    ("lpar", '('),
+    ("rpar", ')'),
+    ("lsqb", '['),
+    ("lbrack", '['),
+    ("rsqb", ']'),
+    ("rbrack", ']'),
+    ("lcub", '{'),
+    ("lbrace", '{'),
+    ("rcub", '}'),
+    ("rbrace", '}'),
+
+    ("amp", '&'),
+    ("AMP", '&'),
+    ("lt", '<'),
+    ("LT", '<'),
+    ("gt", '>'),
+    ("GT", '>'),
+    ("hellip", '\u{2026}'),
+    ("mldr", '\u{2026}'),
+    ("deg", '\u{00B0}'),
+    ("prime", '\u{2032}'),
+    ("Prime", '\u{2033}'),
+];
+
+/// Quotes and brackets that open a quotation or parenthetical.  A straight
+/// quote that comes straight after one of these is an opening quote.
+const OPENING_PUNCTUATION: &[char] = &[
+    '\u{2018}', '\u{201A}', '\u{201B}', '\u{201C}', '\u{201E}', '\u{201F}',
+    '\u{00AB}', '\u{2039}', '\u{300C}', '\u{300E}',
+    OPENING_PRIMARY_QUOTE, OPENING_SECONDARY_QUOTE,
+];
+
+lazy_static! {
+    static ref LEADING_ENTITY_RE: Regex =
+        Regex::new(r"^&#?[[:alnum:]]+;").unwrap();
+
+    static ref TRAILING_ENTITY_RE: Regex =
+        Regex::new(r"&#?[[:alnum:]]+;$").unwrap();
+}
+
+/// The characters either side of a run of text, looking past any inline
+/// tags, e.g. the `n` and `t` either side of the `'` in `<b>don</b>'t`.
+///
+/// `None` means the start or the end of the document.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QuoteContext {
+    pub prev_char: Option<char>,
+    pub next_char: Option<char>,
+}
+
+/// Runs `convert` on `text` with `prev_char` and `next_char` added to
+/// either end, and takes them off again afterwards.
+///
+/// This lets a converter's regexes see what's on the other side of an
+/// inline tag.  `convert` must leave the first and last characters of the
+/// padded text in place.
+pub fn with_context_padding(
+    text: &str,
+    prev_char: &Option<char>,
+    next_char: &Option<char>,
+    convert: impl FnOnce(&str) -> String
+) -> String {
+    let padded = format!(
+        "{}{}{}",
+        prev_char.map_or(String::new(), String::from),
+        text,
+        next_char.map_or(String::new(), String::from)
+    );
+
+    let converted = convert(&padded);
+
+    let mut chars = converted.chars();
+    if prev_char.is_some() {
+        chars.next();
+    }
+    if next_char.is_some() {
+        chars.next_back();
+    }
+    chars.as_str().to_string()
+}
+
+/// Returns the character represented by the HTML entity `entity`,
+/// e.g. `&nbsp;`, `&#160;` or `&#xA0;`.
+///
+/// Returns `None` if this isn't an entity we recognise.
+pub fn decode_entity(entity: &str) -> Option<char> {
+    let name = entity.strip_prefix('&')?.strip_suffix(';')?;
+
+    match name.strip_prefix('#') {
+        Some(number) => {
+            let code_point = match number.strip_prefix('x').or_else(|| number.strip_prefix('X')) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None      => number.parse::<u32>().ok()?,
+            };
+            char::from_u32(code_point)
+        },
+        None => NAMED_ENTITIES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c),
+    }
+}
+
+/// Returns the character represented by `s`, which is either a single
+/// character or an HTML entity.
+pub fn decode(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _               => decode_entity(s),
+    }
+}
+
+/// Returns the first character of `text`, decoding it if `text` starts
+/// with an HTML entity.
+pub fn first_char(text: &str) -> Option<char> {
+    match (*LEADING_ENTITY_RE).find(text) {
+        Some(m) => decode_entity(m.as_str()).or_else(|| text.chars().next()),
+        None    => text.chars().next(),
+    }
+}
+
+/// Returns the last character of `text`, decoding it if `text` ends
+/// with an HTML entity.
+pub fn last_char(text: &str) -> Option<char> {
+    match (*TRAILING_ENTITY_RE).find(text) {
+        Some(m) => decode_entity(m.as_str()).or_else(|| text.chars().last()),
+        None    => text.chars().last(),
+    }
+}
+
+/// Returns true if `c` is a dash, other than a hyphen-minus.
+pub fn is_dash(c: char) -> bool {
+    matches!(c, '\u{2010}'..='\u{2015}' | '\u{2212}' | '\u{2E3A}' | '\u{2E3B}' | '\u{FE58}' | '\u{FE63}' | '\u{FF0D}')
+}
+
+/// Returns true if a quote straight after `c` should open a quotation,
+/// i.e. `c` is whitespace, a dash, or an opening quote.
+pub fn is_opening_context(c: char) -> bool {
+    c.is_whitespace() || is_dash(c) || OPENING_PUNCTUATION.contains(&c)
+}
+
+/// Returns true if a quote straight after `c` can close a quotation.
+///
+/// This is anything except an opening context, an opening bracket,
+/// or a hyphen.
+pub fn is_closing_context(c: char) -> bool {
+    !(is_opening_context(c) || matches!(c, '(' | '[' | '{' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{decode_entity, first_char, is_opening_context, last_char, with_context_padding};
+
+    #[test]
+    fn it_decodes_named_and_numeric_entities() {
+        assert_eq!(decode_entity("&nbsp;"), Some('\u{00A0}'));
+        assert_eq!(decode_entity("&#160;"), Some('\u{00A0}'));
+        assert_eq!(decode_entity("&#xa0;"), Some('\u{00A0}'));
+        assert_eq!(decode_entity("&#X2009;"), Some('\u{2009}'));
+        assert_eq!(decode_entity("&thinsp;"), Some('\u{2009}'));
+        assert_eq!(decode_entity("&notanentity;"), None);
+        assert_eq!(decode_entity("&#xzz;"), None);
+    }
+
+    #[test]
+    fn it_decodes_a_leading_entity() {
+        assert_eq!(first_char("&ldquo;hello"), Some('\u{201C}'));
+        assert_eq!(first_char("hello"), Some('h'));
+        assert_eq!(first_char("&;"), Some('&'));
+        assert_eq!(first_char(""), None);
+    }
+
+    #[test]
+    fn it_decodes_a_trailing_entity() {
+        assert_eq!(last_char("hello&nbsp;"), Some('\u{00A0}'));
+        assert_eq!(last_char("hello;"), Some(';'));
+        assert_eq!(last_char("AT&T;"), Some(';'));
+        assert_eq!(last_char(""), None);
+    }
+
+    #[test]
+    fn it_treats_unicode_whitespace_dashes_and_opening_quotes_as_opening_context() {
+        for c in [' ', '\n', '\u{00A0}', '\u{2009}', '\u{202F}', '\u{2013}', '\u{2014}', '\u{201C}', '\u{00AB}'] {
+            assert!(is_opening_context(c), "{:?}", c);
+        }
+
+        for c in ['a', '.', '-', '\u{201D}', ';'] {
+            assert!(!is_opening_context(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn it_pads_text_with_the_characters_either_side() {
+        let shout = |s: &str| s.to_uppercase();
+
+        assert_eq!(with_context_padding("b", &Some('a'), &Some('c'), |s| { assert_eq!(s, "abc"); shout(s) }), "B");
+        assert_eq!(with_context_padding("b", &None, &Some('é'), |s| { assert_eq!(s, "bé"); shout(s) }), "B");
+        assert_eq!(with_context_padding("b", &Some('«'), &None, |s| { assert_eq!(s, "«b"); shout(s) }), "B");
+        assert_eq!(with_context_padding("", &None, &None, shout), "");
+    }
+}
@@ -1,6 +1,13 @@
-use crate::{SubstitutionConfig, DashesSubstitution, EntitiesSubstitution};
+use crate::{SubstitutionConfig, ApostropheSubstitution, DashesSubstitution, EntitiesSubstitution, PrimesSubstitution, QuotesSubstitution};
+use crate::config::{EllipsesOptions, EllipsisSpaceBinding, EllipsisStyle, FourDotEllipsis};
+use crate::context;
 use crate::entities::*;
+use crate::locale::QuoteStyle;
 use crate::quotes;
+use crate::utils;
+
+use fancy_regex::{Captures as FancyCaptures, Regex as FancyRegex};
+use regex::{Captures, Regex};
 
 /// Apply a series of backslash escapes in `text`.
 ///
@@ -16,6 +23,128 @@ pub fn process_escapes(text: &str) -> String {
         .replace(r"\`",  BACKTICK_ENTITY)
 }
 
+/// Escape the characters in `text` that have a special meaning in HTML.
+///
+/// This is used for plain text input, so the result is safe to embed
+/// in an HTML document.
+pub fn escape_html(text: &str) -> String {
+    text
+        .replace('&', AMPERSAND_ENTITY)
+        .replace('<', LESS_THAN_ENTITY)
+        .replace('>', GREATER_THAN_ENTITY)
+}
+
+/// Escape the ampersands in `text`.
+///
+/// This is used for plain text input with plain text output.  Plain text
+/// doesn't have any entities, so this stops anything the author typed
+/// that looks like one (e.g. `&#8220;`) from being decoded.
+pub fn escape_ampersands(text: &str) -> String {
+    text.replace('&', AMPERSAND_ENTITY)
+}
+
+/// Turn the entities added by `process_escapes` and `escape_ampersands`
+/// back into the characters they stand for.
+///
+/// This is used for plain text output, where an entity would be shown
+/// to the reader as-is.
+pub fn unescape_escapes(text: &str) -> String {
+    text
+        .replace(DOUBLE_STRAIGHT_QUOTE_ENTITY, "\"")
+        .replace(SINGLE_STRAIGHT_QUOTE_ENTITY, "'")
+        .replace(FULL_STOP_ENTITY, ".")
+        .replace(HYPHEN_ENTITY, "-")
+        .replace(BACKTICK_ENTITY, "`")
+        .replace(SINGLE_BACKSLASH_ENTITY, "\\")
+        .replace(AMPERSAND_ENTITY, "&")
+}
+
+/// Turn HTML-escaped quotes in `text` (e.g. `&quot;` or `&#39;`) back
+/// into straight quotes, so they can be curled like any other quote.
+pub fn decode_quote_entities(text: &str) -> String {
+    lazy_static! {
+        static ref DOUBLE_QUOTE_ENTITY_RE: Regex =
+            Regex::new(r"(?i)&(?:quot|#0*34|#x0*22);").unwrap();
+
+        static ref SINGLE_QUOTE_ENTITY_RE: Regex =
+            Regex::new(r"(?i)&(?:apos|#0*39|#x0*27);").unwrap();
+    }
+
+    let text = (*DOUBLE_QUOTE_ENTITY_RE).replace_all(text, "\"");
+    let text = (*SINGLE_QUOTE_ENTITY_RE).replace_all(&text, "'");
+
+    text.to_string()
+}
+
+/// Turn any curly quotes, dashes and ellipses already in `text` back into
+/// the form the other converters expect, so they get re-rendered in the
+/// configured style.
+///
+/// Dashes and ellipses become the entities we'd have produced ourselves.
+/// Curly quotes (and primes being used as quotes) become straight quotes,
+/// so the quote converters can decide again which way they should curl.
+/// Primes that follow a digit are left as-is, because they're probably
+/// being used correctly.
+///
+/// If we're not converting quotes, curly quotes keep the direction they
+/// already have, but are still rendered in the configured entity style.
+pub fn normalize_typography(text: &str, config: &SubstitutionConfig) -> String {
+    lazy_static! {
+        static ref EXISTING_TYPOGRAPHY_RE: Regex =
+            Regex::new("&#?[[:alnum:]]+;|[‘’‚‛“”„‟«»‹›′″–—―…]").unwrap();
+    }
+
+    let convert_quotes = matches!(config.quote_chars, QuotesSubstitution::ConvertToCurly);
+
+    let text = (*EXISTING_TYPOGRAPHY_RE).replace_all(text, |cap: &Captures| {
+        let m = cap.get(0).unwrap();
+        let follows_digit = text[..m.start()].chars().last().is_some_and(|c| c.is_ascii_digit());
+
+        match context::decode(m.as_str()) {
+            Some('–')                        => EN_DASH_ENTITY.to_string(),
+            Some('—' | '―')                  => EM_DASH_ENTITY.to_string(),
+            Some('…')                        => ELLIPSIS_ENTITY.to_string(),
+            Some('′' | '″') if follows_digit => m.as_str().to_string(),
+
+            Some('‘' | '’' | '‚' | '‛' | '‹' | '›' | '′') if convert_quotes => "'".to_string(),
+            Some('“' | '”' | '„' | '‟' | '«' | '»' | '″') if convert_quotes => "\"".to_string(),
+
+            Some(c @ ('‘' | '’' | '‚' | '‛' | '‹' | '›' | '“' | '”' | '„' | '‟' | '«' | '»')) =>
+                render_glyph(c, &config.entities),
+
+            _ => m.as_str().to_string(),
+        }
+    });
+
+    text.to_string()
+}
+
+/// Converts `<<` and `>>` in `text` into guillemet HTML entities.
+///
+/// These might have been escaped as `&lt;&lt;` and `&gt;&gt;` (which
+/// is how they'd appear in plain text that's been escaped for HTML).
+///
+/// We only convert a `<<` that isn't followed by whitespace, or a `>>`
+/// that isn't preceded by whitespace, so e.g. the bit shifts in `1 << 3`
+/// and `x >> y` are left alone, even if they pair up.
+pub fn convert_ascii_guillemets(text: &str) -> String {
+    lazy_static! {
+        static ref OPENING_GUILLEMET_RE: Regex = Regex::new(r"<<|&lt;&lt;").unwrap();
+
+        static ref CLOSING_GUILLEMET_RE: Regex = Regex::new(r">>|&gt;&gt;").unwrap();
+    }
+
+    let is_attached = |c: Option<char>| c.is_none_or(|c| !c.is_whitespace());
+
+    let text = utils::replace_all_with_context(text, &OPENING_GUILLEMET_RE, |_, _, after| {
+        is_attached(after.chars().next()).then(|| OPENING_GUILLEMET_ENTITY.to_string())
+    });
+
+    utils::replace_all_with_context(&text, &CLOSING_GUILLEMET_RE, |_, before, _| {
+        is_attached(before.chars().next_back()).then(|| CLOSING_GUILLEMET_ENTITY.to_string())
+    })
+}
+
 /// Convert `--` and `---` in `text` into HTML entities.
 pub fn convert_dashes(text: &str, config: &SubstitutionConfig) -> String {
     let triple_dash_replacement = match config.triple_dash {
@@ -40,53 +169,207 @@ pub fn convert_dashes(text: &str, config: &SubstitutionConfig) -> String {
         .replace("--", double_dash_replacement)
 }
 
+/// Converts a hyphen with a space either side (` - `) in `text` into
+/// `dash`, which is an HTML entity.
+///
+/// A hyphen at the start of a line is probably a list marker, so we only
+/// convert it if there's something other than whitespace before the space.
+/// `prev_char` is the character before `text`, if any.
+pub fn convert_spaced_hyphens(text: &str, dash: &str, prev_char: &Option<char>) -> String {
+    lazy_static! {
+        static ref SPACED_HYPHEN_RE: FancyRegex =
+            FancyRegex::new(r"(?<=\S)(?P<space>[ \t]+)-(?=[ \t])").unwrap();
+    }
+
+    // Add the previous character to the start of the text, so we can
+    // tell if a hyphen at the start of the text is at the start of a line.
+    context::with_context_padding(text, prev_char, &None, |padded| {
+        (*SPACED_HYPHEN_RE).replace_all(padded, |caps: &FancyCaptures| {
+            format!("{}{}", &caps["space"], dash)
+        })
+        .to_string()
+    })
+}
+
 /// Converts `...` in `text` into ellipsis HTML entities.
 pub fn convert_ellipses(text: &str) -> String {
-    text
-        .replace("...", ELLIPSIS_ENTITY)
-        .replace(". . .", ELLIPSIS_ENTITY)
+    convert_ellipses_with_options(text, &EllipsesOptions::default())
 }
 
-/// Converts ```double backticks''`-style quotes in `text` into HTML curly quote entities.
+/// Converts `...` and `. . .` in `text` into ellipses, written in the
+/// style given by `options`.
+pub fn convert_ellipses_with_options(text: &str, options: &EllipsesOptions) -> String {
+    lazy_static! {
+        static ref ELLIPSIS_RE: FancyRegex = FancyRegex::new(
+            r"(?P<space>[ \t]+)?(?:\.\.\.(?P<fourth>\.(?!\.\.))?|\. \. \.(?P<spaced_fourth> \.(?! \. \.))?)").unwrap();
+    }
+
+    let ellipsis = match options.style {
+        EllipsisStyle::Character     => ELLIPSIS_ENTITY.to_string(),
+        EllipsisStyle::SpacedPeriods => [".", ".", "."].join(NO_BREAK_SPACE_ENTITY),
+    };
+
+    (*ELLIPSIS_RE)
+        .replace_all(text, |caps: &FancyCaptures| {
+            let space = match (caps.name("space"), &options.space_before) {
+                (Some(_), EllipsisSpaceBinding::NoBreakSpace) => NO_BREAK_SPACE_ENTITY,
+                (Some(m), EllipsisSpaceBinding::DoNothing)    => m.as_str(),
+                (None, _)                                     => "",
+            };
+
+            let fourth = caps.name("fourth").or(caps.name("spaced_fourth"));
+
+            match (fourth, &options.four_dots, &options.style) {
+                (None, _, _) =>
+                    format!("{}{}", space, ellipsis),
+                (Some(_), FourDotEllipsis::Ellipsis, _) =>
+                    format!("{}{}", space, ellipsis),
+                (Some(_), FourDotEllipsis::PeriodThenEllipsis, EllipsisStyle::Character) =>
+                    format!("{}.{}", space, ellipsis),
+                (Some(_), FourDotEllipsis::PeriodThenEllipsis, EllipsisStyle::SpacedPeriods) =>
+                    format!("{}. {}", space, ellipsis),
+                (Some(_), FourDotEllipsis::EllipsisThenPeriod, EllipsisStyle::SpacedPeriods) =>
+                    format!("{}{}{}.", space, ellipsis, NO_BREAK_SPACE_ENTITY),
+                (Some(m), FourDotEllipsis::EllipsisThenPeriod, EllipsisStyle::Character) =>
+                    format!("{}{}{}", space, ellipsis, m.as_str()),
+            }
+        })
+        .to_string()
+}
+
+/// Converts ```double backticks''`-style quotes in `text` into curly quotes.
 pub fn convert_double_backticks(text: &str) -> String {
     text
-        .replace("``", OPENING_DOUBLE_CURLY_QUOTE_ENTITY)
-        .replace("''", CLOSING_DOUBLE_CURLY_QUOTE_ENTITY)
+        .replace("``", &OPENING_PRIMARY_QUOTE.to_string())
+        .replace("''", &CLOSING_PRIMARY_QUOTE.to_string())
 }
 
-/// Converts ``single backticks'`-style quotes in `text` into HTML curly quote entities.
+/// Converts ``single backticks'`-style quotes in `text` into curly quotes.
+///
+/// A `'` between two letters is an apostrophe rather than a closing quote.
 pub fn convert_single_backticks(text: &str) -> String {
-    text
-        .replace("`", OPENING_SINGLE_CURLY_QUOTE_ENTITY)
-        .replace("'", CLOSING_SINGLE_CURLY_QUOTE_ENTITY)
-}
-
-/// Converts normal quotes (`"` and `'`) into HTML curly quote entities.
-pub fn convert_quotes(text: &str, prev_token_last_char: &Option<char>) -> String {
-    match text {
-        "\'" => quotes::handle_single_straight_quote_token(prev_token_last_char),
-        "\"" => quotes::handle_double_straight_quote_token(prev_token_last_char),
-
-        _ => {
-            let text = quotes::handle_leading_quote_with_punctuation(&text);
-            let text = quotes::handle_double_sets_of_quotes(&text);
-            let text = quotes::handle_decade_abbreviations(&text);
-
-            println!("4 = {}", text);
-            let text = quotes::handle_opening_single_quotes(&text);
-            println!("5 = {}", text);
-            let text = quotes::handle_closing_single_quotes(&text);
-            println!("6 = {}", text);
-            let text = quotes::handle_remaining_single_quotes(&text);
-            println!("7 = {}", text);
-
-            let text = quotes::handle_opening_double_quotes(&text);
-            let text = quotes::handle_closing_double_quotes(&text);
-            let text = quotes::handle_remaining_double_quotes(&text);
-
-            text.to_string()
+    let text = text.replace('`', &OPENING_SECONDARY_QUOTE.to_string());
+
+    let mut result = String::with_capacity(text.len());
+    let mut prev_char = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            let is_apostrophe = prev_char.is_some_and(char::is_alphanumeric)
+                && chars.peek().is_some_and(|next| next.is_alphanumeric());
+
+            result.push(if is_apostrophe { APOSTROPHE } else { CLOSING_SECONDARY_QUOTE });
+        } else {
+            result.push(c);
+        }
+
+        prev_char = Some(c);
+    }
+
+    result
+}
+
+/// Converts normal quotes (`"` and `'`) into curly quote placeholders.
+///
+/// Any words in `elisions` (e.g. `'tis`) get apostrophes rather than quotes,
+/// and quotes after digits may become primes (e.g. `5'10"`), if enabled.
+///
+/// `prev_char` and `next_char` are the characters either side of `text`,
+/// e.g. if it's between two inline tags.  The quote regexes only look at
+/// the text they're given, so we add these characters to either end,
+/// convert the quotes, then take them off again.
+///
+/// We also return which of the straight quotes in `text` became primes,
+/// counting from 0.
+pub fn convert_quotes(text: &str, elisions: &[&str], primes: &PrimesSubstitution, prev_char: &Option<char>, next_char: &Option<char>) -> (String, Vec<usize>) {
+    let mut converted_primes = vec![];
+
+    // Each of the quote handlers leaves the character before a quote
+    // as-is, and turns a quote into a single placeholder, so the padding
+    // is still one character at either end.
+    let text = context::with_context_padding(text, prev_char, next_char, |padded| {
+        // Note: primes need to be found before any of the other quote
+        // handlers run, because they'd curl them.
+        let text_range = prev_char.map_or(0, char::len_utf8)..(padded.len() - next_char.map_or(0, char::len_utf8));
+
+        let text = match primes {
+            PrimesSubstitution::ConvertToPrimes => {
+                let (text, primes) = quotes::handle_primes(padded, text_range);
+                converted_primes = primes;
+                text
+            },
+            PrimesSubstitution::DoNothing => padded.to_string(),
+        };
+
+        let text = quotes::handle_leading_quote_with_punctuation(&text);
+        let text = quotes::handle_double_sets_of_quotes(&text, prev_char.is_none());
+        let text = quotes::handle_decade_abbreviations(&text);
+        let text = quotes::handle_elisions(&text, elisions);
+
+        let text = quotes::handle_opening_single_quotes(&text);
+        let text = quotes::handle_closing_single_quotes(&text);
+        let text = quotes::handle_remaining_single_quotes(&text);
+
+        let text = quotes::handle_opening_double_quotes(&text);
+        let text = quotes::handle_closing_double_quotes(&text);
+        quotes::handle_remaining_double_quotes(&text)
+    });
+
+    (text, converted_primes)
+}
+
+/// Replaces the quote placeholders in `text` with the glyphs for `style`,
+/// rendered in the chosen entity style.
+///
+/// Apostrophes don't depend on the locale, so they get the glyph chosen
+/// by `apostrophe_substitution` instead.
+pub fn render_quotes(
+    text: &str,
+    style: &QuoteStyle,
+    apostrophe_substitution: &ApostropheSubstitution,
+    entities_substitution: &EntitiesSubstitution
+) -> String {
+    let apostrophe = match apostrophe_substitution {
+        ApostropheSubstitution::RightSingleQuote          => '’',
+        ApostropheSubstitution::ModifierLetterApostrophe  => 'ʼ',
+        ApostropheSubstitution::ModifierLetterTurnedComma => 'ʻ',
+    };
+
+    let mut result = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        let glyph = match c {
+            APOSTROPHE => Some(apostrophe),
+            _          => style.glyph_for(c),
+        };
+
+        match glyph {
+            Some(glyph) => result.push_str(&render_glyph(glyph, entities_substitution)),
+            None        => result.push(c),
         }
     }
+
+    result
+}
+
+/// Renders a single glyph in the chosen entity style.
+///
+/// If the glyph doesn't have a named entity, we use a numeric entity.
+pub fn render_glyph(glyph: char, entities_substitution: &EntitiesSubstitution) -> String {
+    let (named, ascii) = GLYPHS
+        .iter()
+        .find(|(c, _, _)| *c == glyph)
+        .map_or((None, None), |(_, named, ascii)| (*named, Some(*ascii)));
+
+    match entities_substitution {
+        EntitiesSubstitution::UnicodeCharacters   => glyph.to_string(),
+        EntitiesSubstitution::HtmlNumericEntities => format!("&#{};", glyph as u32),
+        EntitiesSubstitution::HtmlNamedEntities   =>
+            named.map_or_else(|| format!("&#{};", glyph as u32), str::to_string),
+        EntitiesSubstitution::AsciiEquivalents    =>
+            ascii.map_or_else(|| glyph.to_string(), str::to_string),
+    }
 }
 
 /// Converts numeric character references to other entities, if desired.
@@ -97,33 +380,15 @@ pub fn convert_quotes(text: &str, prev_token_last_char: &Option<char>) -> String
 /// of SmartyPants much more substantially.
 pub fn convert_entities(text: &str, entities_substitution: &EntitiesSubstitution) -> String {
     match entities_substitution {
-        EntitiesSubstitution::UnicodeCharacters =>
-            text
-                .replace(EN_DASH_ENTITY, "–")
-                .replace(EM_DASH_ENTITY, "—")
-                .replace(OPENING_SINGLE_CURLY_QUOTE_ENTITY, "‘")
-                .replace(CLOSING_SINGLE_CURLY_QUOTE_ENTITY, "’")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "“")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "”"),
-
         EntitiesSubstitution::HtmlNumericEntities => text.to_string(),
 
-        EntitiesSubstitution::HtmlNamedEntities =>
-            text
-                .replace(EN_DASH_ENTITY, "&ndash;")
-                .replace(EM_DASH_ENTITY, "&mdash;")
-                .replace(OPENING_SINGLE_CURLY_QUOTE_ENTITY, "&lsquo;")
-                .replace(CLOSING_SINGLE_CURLY_QUOTE_ENTITY, "&rsquo;")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "&ldquo;")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "&rdquo;"),
-
-        EntitiesSubstitution::AsciiEquivalents =>
-            text
-                .replace(EN_DASH_ENTITY, "-")
-                .replace(EM_DASH_ENTITY, "--")
-                .replace(OPENING_SINGLE_CURLY_QUOTE_ENTITY, "'")
-                .replace(CLOSING_SINGLE_CURLY_QUOTE_ENTITY, "'")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "\"")
-                .replace(OPENING_DOUBLE_CURLY_QUOTE_ENTITY, "\""),
+        _ => GLYPHS
+            .iter()
+            .fold(text.to_string(), |text, (glyph, _, _)| {
+                text.replace(
+                    &format!("&#{};", *glyph as u32),
+                    &render_glyph(*glyph, entities_substitution)
+                )
+            }),
     }
 }
@@ -2,14 +2,21 @@
 //
 // I pulled them into named entities to make the code that uses them
 // easier to read, because I don't have them memorised.  The name
-// OPENING_DOUBLE_CURLY_QUOTE_ENTITY means something, but not "&#8220;".
+// OPENING_GUILLEMET_ENTITY means something, but not "&#171;".
+//
+// It also contains the placeholders used by the quote converters, and
+// the table we use to render glyphs in the chosen entity style.
+
+pub const SINGLE_STRAIGHT_QUOTE_ENTITY: &str = "&#39;";         // '
+pub const DOUBLE_STRAIGHT_QUOTE_ENTITY: &str = "&#34;";         // "
 
-pub const SINGLE_STRAIGHT_QUOTE_ENTITY: &str = "&#39";          // '
-pub const DOUBLE_STRAIGHT_QUOTE_ENTITY: &str = "&#34";          // "
+pub const AMPERSAND_ENTITY: &str = "&amp;";                     // &
+pub const LESS_THAN_ENTITY: &str = "&lt;";                      // <
+pub const GREATER_THAN_ENTITY: &str = "&gt;";                   // >
 
 pub const HYPHEN_ENTITY: &str = "&#45;";                        // -
 
-pub const FULL_STOP_ENTITY: &str = "&#46";                      // .
+pub const FULL_STOP_ENTITY: &str = "&#46;";                     // .
 
 pub const SINGLE_BACKSLASH_ENTITY: &str = "&#92;";              // \
 
@@ -20,11 +27,112 @@ pub const EM_DASH_ENTITY: &str = "&#8212;";                     // —
 
 pub const ELLIPSIS_ENTITY: &str = "&#8230;";                    // …
 
-pub const EN_DASH_HEX_ENTITY: &str = "&#x2013;";                // –
-pub const EM_DASH_HEX_ENTITY: &str = "&#x2014;";                // —
+pub const MINUS_ENTITY: &str = "&#8722;";                       // −
+pub const MULTIPLICATION_ENTITY: &str = "&#215;";               // ×
+pub const PLUS_MINUS_ENTITY: &str = "&#177;";                   // ±
+
+pub const COPYRIGHT_ENTITY: &str = "&#169;";                    // ©
+pub const REGISTERED_ENTITY: &str = "&#174;";                   // ®
+pub const TRADEMARK_ENTITY: &str = "&#8482;";                   // ™
+pub const SOUND_RECORDING_ENTITY: &str = "&#8471;";             // ℗
+pub const CARE_OF_ENTITY: &str = "&#8453;";                     // ℅
+
+pub const LEFTWARDS_ARROW_ENTITY: &str = "&#8592;";             // ←
+pub const RIGHTWARDS_ARROW_ENTITY: &str = "&#8594;";            // →
+pub const LEFT_RIGHT_ARROW_ENTITY: &str = "&#8596;";            // ↔
+pub const RIGHTWARDS_DOUBLE_ARROW_ENTITY: &str = "&#8658;";     // ⇒
+pub const LEFT_RIGHT_DOUBLE_ARROW_ENTITY: &str = "&#8660;";     // ⇔
+pub const NOT_EQUAL_ENTITY: &str = "&#8800;";                   // ≠
+pub const LESS_THAN_OR_EQUAL_ENTITY: &str = "&#8804;";          // ≤
+pub const GREATER_THAN_OR_EQUAL_ENTITY: &str = "&#8805;";       // ≥
+
+pub const NO_BREAK_SPACE_ENTITY: &str = "&#160;";               // no-break space
+pub const HAIR_SPACE_ENTITY: &str = "&#8202;";                  // hair space
+pub const NARROW_NO_BREAK_SPACE_ENTITY: &str = "&#8239;";       // narrow no-break space
+
+pub const PRIME_ENTITY: &str = "&#8242;";                       // ′
+pub const DOUBLE_PRIME_ENTITY: &str = "&#8243;";                // ″
+
+pub const OPENING_GUILLEMET_ENTITY: &str = "&#171;";            // «
+pub const CLOSING_GUILLEMET_ENTITY: &str = "&#187;";            // »
+
+// These aren't HTML entities, but placeholders for the decisions made by
+// the quote converters.  They come from a Private Use Area of Unicode, so
+// they won't clash with anything in the input, and they get replaced by
+// the glyphs for the configured locale in `converters::render_quotes`.
+pub const OPENING_PRIMARY_QUOTE: char = '\u{F0001}';             // “ in English
+pub const CLOSING_PRIMARY_QUOTE: char = '\u{F0002}';             // ” in English
+pub const OPENING_SECONDARY_QUOTE: char = '\u{F0003}';           // ‘ in English
+pub const CLOSING_SECONDARY_QUOTE: char = '\u{F0004}';           // ’ in English
+pub const APOSTROPHE: char = '\u{F0005}';                        // ’
+
+// Spaces that we might insert.
+pub const NARROW_NO_BREAK_SPACE: char = '\u{202F}';
+
+// The glyphs we might produce, with their named HTML entity (if there
+// is one) and their ASCII equivalent.
+pub const GLYPHS: &[(char, Option<&str>, &str)] = &[
+    ('–', Some("&ndash;"),  "-"),
+    ('—', Some("&mdash;"),  "--"),
+    ('…', Some("&hellip;"), "..."),
+    ('−', Some("&minus;"),  "-"),
+    ('×', Some("&times;"),  "x"),
+    ('±', Some("&plusmn;"), "+/-"),
+    ('©', Some("&copy;"),   "(c)"),
+    ('®', Some("&reg;"),    "(r)"),
+    ('™', Some("&trade;"),  "(tm)"),
+    ('℗', Some("&copysr;"), "(p)"),
+    ('℅', Some("&incare;"), "c/o"),
+    ('←', Some("&larr;"),   "<-"),
+    ('→', Some("&rarr;"),   "->"),
+    ('↔', Some("&harr;"),   "<->"),
+    ('⇒', Some("&rArr;"),   "=>"),
+    ('⇔', Some("&hArr;"),   "<=>"),
+    ('≠', Some("&ne;"),     "!="),
+    ('≤', Some("&le;"),     "<="),
+    ('≥', Some("&ge;"),     ">="),
+    ('½', Some("&frac12;"), "1/2"),
+    ('⅓', Some("&frac13;"), "1/3"),
+    ('⅔', Some("&frac23;"), "2/3"),
+    ('¼', Some("&frac14;"), "1/4"),
+    ('¾', Some("&frac34;"), "3/4"),
+    ('⅕', Some("&frac15;"), "1/5"),
+    ('⅖', Some("&frac25;"), "2/5"),
+    ('⅗', Some("&frac35;"), "3/5"),
+    ('⅘', Some("&frac45;"), "4/5"),
+    ('⅙', Some("&frac16;"), "1/6"),
+    ('⅚', Some("&frac56;"), "5/6"),
+    ('⅐', None,             "1/7"),
+    ('⅛', Some("&frac18;"), "1/8"),
+    ('⅜', Some("&frac38;"), "3/8"),
+    ('⅝', Some("&frac58;"), "5/8"),
+    ('⅞', Some("&frac78;"), "7/8"),
+    ('⅑', None,             "1/9"),
+    ('⅒', None,             "1/10"),
+    ('⁄', Some("&frasl;"),  "/"),
+    ('º', Some("&ordm;"),   "o"),
+    ('ª', Some("&ordf;"),   "a"),
+    ('′', Some("&prime;"),  "'"),
+    ('″', Some("&Prime;"),  "\""),
 
-pub const OPENING_SINGLE_CURLY_QUOTE_ENTITY: &str = "&#8216;";  // ‘
-pub const CLOSING_SINGLE_CURLY_QUOTE_ENTITY: &str = "&#8217;";  // ’
+    ('‘', Some("&lsquo;"),  "'"),
+    ('’', Some("&rsquo;"),  "'"),
+    ('‚', Some("&sbquo;"),  "'"),
+    ('‹', Some("&lsaquo;"), "'"),
+    ('›', Some("&rsaquo;"), "'"),
+    ('“', Some("&ldquo;"),  "\""),
+    ('”', Some("&rdquo;"),  "\""),
+    ('„', Some("&bdquo;"),  "\""),
+    ('«', Some("&laquo;"),  "\""),
+    ('»', Some("&raquo;"),  "\""),
+    ('「', None,             "\""),
+    ('」', None,             "\""),
+    ('『', None,             "'"),
+    ('』', None,             "'"),
+    ('ʼ', None,             "'"),
+    ('ʻ', None,             "'"),
 
-pub const OPENING_DOUBLE_CURLY_QUOTE_ENTITY: &str = "&#8220;";  // “
-pub const CLOSING_DOUBLE_CURLY_QUOTE_ENTITY: &str = "&#8221;";  // ”
+    ('\u{A0}',   Some("&nbsp;"),   " "),
+    ('\u{200A}', Some("&hairsp;"), " "),
+    ('\u{202F}', None,             " "),
+];
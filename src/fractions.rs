@@ -0,0 +1,157 @@
+// This file contains the code for converting fractions, e.g. `1/2` or
+// `2 1/3`, into vulgar fractions.
+//
+// Unicode has precomposed characters for the common fractions (`½`, `⅓`,
+// `¾`, ...), which we use where we can.  Any other fraction is written with
+// a fraction slash (U+2044) between a raised numerator and a lowered
+// denominator -- using `<sup>` and `<sub>` in HTML, or the superscript and
+// subscript digits if we're writing Unicode characters.
+//
+// Lots of things with a slash between two numbers aren't fractions, e.g.
+// dates (`1/2/2022`), paths and URLs (`/img/1/2`), or `24/7`.  We only
+// convert a proper fraction (where the numerator is smaller than the
+// denominator) which stands on its own, rather than being part of a word
+// (`1/2a`) or a longer run of digits and slashes.
+//
+// We can't tell a day and month written without a year (`9/11`) from a
+// fraction, so those are converted too -- which is one reason this is off
+// by default.
+
+use fancy_regex::{Captures, Regex as FancyRegex};
+
+use crate::config::EntitiesSubstitution;
+
+lazy_static! {
+    static ref FRACTION_RE: FancyRegex = FancyRegex::new(
+        r"(?<![\w/.])(?:(?P<whole>\d+) )?(?P<numerator>\d+)/(?P<denominator>\d+)(?![\w/]|[.,]\d)"
+    ).unwrap();
+}
+
+const PRECOMPOSED_FRACTIONS: &[(&str, &str, char)] = &[
+    ("1", "2", '½'),
+    ("1", "3", '⅓'),
+    ("2", "3", '⅔'),
+    ("1", "4", '¼'),
+    ("3", "4", '¾'),
+    ("1", "5", '⅕'),
+    ("2", "5", '⅖'),
+    ("3", "5", '⅗'),
+    ("4", "5", '⅘'),
+    ("1", "6", '⅙'),
+    ("5", "6", '⅚'),
+    ("1", "7", '⅐'),
+    ("1", "8", '⅛'),
+    ("3", "8", '⅜'),
+    ("5", "8", '⅝'),
+    ("7", "8", '⅞'),
+    ("1", "9", '⅑'),
+    ("1", "10", '⅒'),
+];
+
+const FRACTION_SLASH: char = '\u{2044}';
+
+const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+const SUBSCRIPT_DIGITS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+
+/// Converts the fractions in `text` into vulgar fractions.
+///
+/// The precomposed fractions become HTML entities, like the other
+/// converters; how we write any other fraction depends on
+/// `entities_substitution`.  In ASCII, we leave the fractions as-is.
+///
+/// If `allow_markup` is false (e.g. in an attribute value), we use the
+/// superscript and subscript digits rather than `<sup>` and `<sub>`.
+pub fn convert_fractions(text: &str, entities_substitution: &EntitiesSubstitution, allow_markup: bool) -> String {
+    if matches!(entities_substitution, EntitiesSubstitution::AsciiEquivalents) {
+        return text.to_string();
+    }
+
+    (*FRACTION_RE)
+        .replace_all(text, |caps: &Captures| {
+            let (numerator, denominator) = (&caps["numerator"], &caps["denominator"]);
+
+            if !is_proper_fraction(numerator, denominator) {
+                return caps[0].to_string();
+            }
+
+            // A whole number is joined up to the fraction, e.g. `2⅓`.
+            let whole = caps.name("whole").map_or("", |m| m.as_str());
+
+            format!("{}{}", whole, render_fraction(numerator, denominator, entities_substitution, allow_markup))
+        })
+        .to_string()
+}
+
+fn render_fraction(numerator: &str, denominator: &str, entities_substitution: &EntitiesSubstitution, allow_markup: bool) -> String {
+    let precomposed = PRECOMPOSED_FRACTIONS
+        .iter()
+        .find(|(n, d, _)| *n == numerator && *d == denominator);
+
+    if let Some((_, _, c)) = precomposed {
+        return to_entity(*c);
+    }
+
+    let is_unicode = matches!(entities_substitution, EntitiesSubstitution::UnicodeCharacters);
+
+    if allow_markup && !is_unicode {
+        return format!("<sup>{}</sup>{}<sub>{}</sub>", numerator, to_entity(FRACTION_SLASH), denominator);
+    }
+
+    let fraction = format!(
+        "{}{}{}",
+        to_digits(numerator, &SUPERSCRIPT_DIGITS),
+        FRACTION_SLASH,
+        to_digits(denominator, &SUBSCRIPT_DIGITS),
+    );
+
+    if is_unicode {
+        fraction
+    } else {
+        fraction.chars().map(to_entity).collect()
+    }
+}
+
+fn to_entity(c: char) -> String {
+    format!("&#{};", c as u32)
+}
+
+fn to_digits(number: &str, digits: &[char; 10]) -> String {
+    number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| digits[d as usize])
+        .collect()
+}
+
+fn is_proper_fraction(numerator: &str, denominator: &str) -> bool {
+    match (numerator.parse::<u64>(), denominator.parse::<u64>()) {
+        (Ok(n), Ok(d)) => n > 0 && n < d,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::EntitiesSubstitution;
+    use crate::fractions::convert_fractions;
+
+    #[test]
+    fn it_converts_fractions() {
+        let numeric = EntitiesSubstitution::HtmlNumericEntities;
+
+        assert_eq!(convert_fractions("1/2 cup", &numeric, true), "&#189; cup");
+        assert_eq!(convert_fractions("2 1/3 cups", &numeric, true), "2&#8531; cups");
+        assert_eq!(convert_fractions("a 3/4 in bolt", &numeric, true), "a &#190; in bolt");
+        assert_eq!(convert_fractions("3/16 inch", &numeric, true), "<sup>3</sup>&#8260;<sub>16</sub> inch");
+        assert_eq!(convert_fractions("3/16 inch", &numeric, false), "&#179;&#8260;&#8321;&#8326; inch");
+        assert_eq!(convert_fractions("3/16", &EntitiesSubstitution::UnicodeCharacters, true), "³⁄₁₆");
+        assert_eq!(convert_fractions("3/16", &EntitiesSubstitution::AsciiEquivalents, true), "3/16");
+    }
+
+    #[test]
+    fn it_leaves_things_that_arent_fractions() {
+        for text in ["1/2/2022", "/img/1/2", "a1/2", "1/2a", "3/4in", "24/7", "50/50", "0/4", "1.5/2"] {
+            assert_eq!(convert_fractions(text, &EntitiesSubstitution::HtmlNumericEntities, true), text);
+        }
+    }
+}
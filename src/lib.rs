@@ -3,19 +3,43 @@ extern crate lazy_static;
 
 use regex::Regex;
 
+mod attributes;
 pub mod config;
+mod context;
 pub mod converters;
 mod entities;
+mod fractions;
+pub mod locale;
+mod nesting;
+mod no_break_spaces;
+mod numbers;
+mod ordinals;
+mod q_elements;
 mod quotes;
+mod spacing;
+mod symbols;
 mod tokenize;
 mod tests;
 mod utils;
+pub mod warnings;
 
-use config::{SubstitutionConfig, DashesSubstitution, EllipsesSubstitution, EntitiesSubstitution, QuotesSubstitution};
+use config::{SubstitutionConfig, ApostropheSubstitution, DashesSubstitution, EllipsesSubstitution, EntitiesSubstitution, GuillemetsSubstitution, InputFormat, NormalizationSubstitution, OrdinalsSubstitution, PrimesSubstitution, QElementsSubstitution, QuotesSubstitution, SpacingSubstitution, SymbolsSubstitution};
+use context::QuoteContext;
+use locale::Locale;
+use nesting::QuoteStack;
+use q_elements::{OpenQ, QTag};
 use tokenize::Token;
+use warnings::Warning;
 
-fn handle_tag_token(contents: String, result: &mut Vec<String>, skipped_tag_stack: &mut Vec<String>) -> () {
-    result.push(contents.to_owned());
+fn handle_tag_token(contents: String, config: &SubstitutionConfig, locale: &Locale, result: &mut Vec<String>, skipped_tag_stack: &mut Vec<String>) {
+
+    // Tags are passed through untouched, unless we've been asked to
+    // smarten some of their attribute values.
+    if config.smartened_attributes.is_empty() || !skipped_tag_stack.is_empty() {
+        result.push(contents.to_owned());
+    } else {
+        result.push(attributes::smarten_attributes(&contents, config, locale));
+    }
 
     // This is used to match tags where we don't want to do any corrections.
     //
@@ -65,80 +89,443 @@ fn handle_tag_token(contents: String, result: &mut Vec<String>, skipped_tag_stac
     // and so if this closing tag doesn't match the last
     // open on the stack, we also ignore it here.
     //
-    else if !skipped_tag_stack.is_empty() {
-        if skipped_tag_stack.last().unwrap() == tag_name {
-            skipped_tag_stack.pop();
+    else if !skipped_tag_stack.is_empty() && skipped_tag_stack.last().unwrap() == tag_name {
+        skipped_tag_stack.pop();
+    }
+}
+
+/// Track the language of the text, using the `lang` and `xml:lang`
+/// attributes on HTML elements.
+///
+/// We keep a stack of elements that set a language, and the language
+/// of any text is the one set by the innermost element on the stack.
+fn handle_lang_attribute(contents: &str, config: &SubstitutionConfig, lang_stack: &mut Vec<(String, Locale)>) {
+    lazy_static! {
+        static ref TAG_NAME_REGEX: Regex = Regex::new(
+            r"^<(?P<closing_slash>/)?(?P<tag_name>[A-Za-z][^\s/>]*)").unwrap();
+
+        static ref LANG_REGEX: Regex = Regex::new(
+            r#"(?i)\s(?:xml:)?lang\s*=\s*(?:"(?P<double>[^"]*)"|'(?P<single>[^']*)'|(?P<unquoted>[^\s"'=<>`]+))"#).unwrap();
+    }
+
+    // Void elements never have any text inside them, and never get
+    // a closing tag, so we don't need to track their language.
+    const VOID_ELEMENTS: &[&str] = &[
+        "area", "base", "br", "col", "embed", "hr", "img", "input",
+        "link", "meta", "param", "source", "track", "wbr",
+    ];
+
+    let tag_match = match (*TAG_NAME_REGEX).captures(contents) {
+        Some(m) => m,
+        None    => return,
+    };
+
+    let tag_name = tag_match["tag_name"].to_lowercase();
+    let is_closing_tag = tag_match.name("closing_slash").is_some();
+
+    // As with the skipped tag stack, we ignore closing tags that don't
+    // match the last open element on the stack.
+    if is_closing_tag {
+        if lang_stack.last().is_some_and(|(name, _)| *name == tag_name) {
+            lang_stack.pop();
         }
+        return;
+    }
+
+    if VOID_ELEMENTS.contains(&tag_name.as_str()) || contents.ends_with("/>") {
+        return;
+    }
+
+    if let Some(lang_match) = (*LANG_REGEX).captures(contents) {
+        let tag = lang_match.name("double")
+            .or_else(|| lang_match.name("single"))
+            .or_else(|| lang_match.name("unquoted"))
+            .unwrap()
+            .as_str();
+
+        // An empty `lang` means the language is unknown, so we fall back
+        // to the configured locale.
+        let locale = if tag.trim().is_empty() {
+            config.locale.clone()
+        } else {
+            Locale::new(tag)
+        };
+
+        lang_stack.push((tag_name, locale));
     }
 }
 
-fn handle_text_token(text: String, config: &SubstitutionConfig, prev_token_last_char: &mut Option<char>, result: &mut Vec<String>, in_skipped_tag: bool) -> () {
+/// Returns true if we should add French-style spaces around punctuation
+/// in text in `locale`.
+fn uses_french_spacing(config: &SubstitutionConfig, locale: &Locale) -> bool {
+    match config.punctuation_spacing {
+        SpacingSubstitution::DoNothing  => false,
+        SpacingSubstitution::FromLocale => locale.uses_french_spacing(),
+        SpacingSubstitution::French     => true,
+    }
+}
 
-    // Remember the last character of this token before processing.
-    //
-    // We know that text-tokens are non-empty, so the unwrap() is safe here.
-    let this_token_last_char = text.chars().last().unwrap();
+/// Apply all the configured conversions to a run of text.
+///
+/// This is used for text tokens, and for any attribute values we've
+/// been asked to smarten.  `allow_markup` is false where we can't add any
+/// tags, i.e. in attribute values and plain text output.
+pub(crate) fn convert_text(text: &str, config: &SubstitutionConfig, locale: &Locale, quote_context: &QuoteContext, quote_stack: &mut QuoteStack, allow_markup: bool) -> String {
+    // Note: escaped quotes need to be decoded before we process backslash
+    // escapes, because a backslash-escaped quote becomes an HTML entity
+    // and we want that one to stay dumb.
+    let text = match (&config.escaped_quotes, &config.quote_chars) {
+        (QuotesSubstitution::ConvertToCurly, QuotesSubstitution::ConvertToCurly) =>
+            converters::decode_quote_entities(text),
+        _ => text.to_string(),
+    };
+
+    let text = converters::process_escapes(&text);
+
+    let text = match config.ascii_guillemets {
+        GuillemetsSubstitution::ConvertToGuillemets => converters::convert_ascii_guillemets(&text),
+        GuillemetsSubstitution::DoNothing           => text,
+    };
+
+    let text = match config.normalization {
+        NormalizationSubstitution::Normalize => converters::normalize_typography(&text, config),
+        NormalizationSubstitution::DoNothing => text,
+    };
+
+    // Note: arrows need to be processed before dashes.
+    let text = symbols::convert_arrows(&text, config);
+
+    let text = converters::convert_dashes(&text, config);
+
+    let text = match config.single_spaced_hyphen {
+        DashesSubstitution::DoNothing => text,
+        DashesSubstitution::EnDash    => converters::convert_spaced_hyphens(&text, entities::EN_DASH_ENTITY, &quote_context.prev_char),
+        DashesSubstitution::EmDash    => converters::convert_spaced_hyphens(&text, entities::EM_DASH_ENTITY, &quote_context.prev_char),
+    };
+
+    let text = numbers::convert_numbers(&text, config);
+
+    let text = symbols::convert_symbols(&text, config);
+
+    let text = spacing::apply_dash_spacing(&text, config, &quote_context.prev_char, &quote_context.next_char);
+
+    let text = match config.ellipses {
+        EllipsesSubstitution::ConvertToEntity     => converters::convert_ellipses(&text),
+        EllipsesSubstitution::Custom(ref options) => converters::convert_ellipses_with_options(&text, options),
+        EllipsesSubstitution::DoNothing           => text,
+    };
+
+    // Note: no-break spaces are inserted after dashes and ellipses, so the
+    // rules can see the dashes and ellipses we've made.
+    let text = no_break_spaces::insert_no_break_spaces(&text, &config.no_break_spaces, locale, &quote_context.prev_char, &quote_context.next_char);
+
+    // Note: backticks need to be processed before quotes, and double
+    // backticks need to be processed before single backticks.
+
+    let text = match config.double_backticks {
+        QuotesSubstitution::ConvertToCurly => converters::convert_double_backticks(&text),
+        QuotesSubstitution::DoNothing      => text,
+    };
+
+    let text = match config.single_backticks {
+        QuotesSubstitution::ConvertToCurly => converters::convert_single_backticks(&text),
+        QuotesSubstitution::DoNothing      => text,
+    };
+
+    let text = match config.quote_chars {
+        QuotesSubstitution::ConvertToCurly => {
+            let elisions: Vec<&str> = locale.elisions()
+                .into_iter()
+                .chain(config.elisions.iter().map(String::as_str))
+                .collect();
+
+            let (text, primes) = converters::convert_quotes(&text, &elisions, &config.primes, &quote_context.prev_char, &quote_context.next_char);
+            quote_stack.skip_primes(&primes);
+            text
+        },
+        QuotesSubstitution::DoNothing => text,
+    };
+
+    let text = quote_stack.apply(&text, config);
+
+    // Note: fractions and ordinals are converted after quotes, so the quote
+    // converters don't see any `<sup>` or `<sub>` tags we add, and e.g. the
+    // `"` in `1/2"` is still seen as coming after a digit.
+    let text = match config.fractions {
+        SymbolsSubstitution::ConvertToSymbol => fractions::convert_fractions(&text, &config.entities, allow_markup),
+        SymbolsSubstitution::DoNothing       => text,
+    };
+
+    let text = match config.ordinals {
+        OrdinalsSubstitution::Superscript => ordinals::convert_ordinals(&text, locale, &config.entities, allow_markup),
+        OrdinalsSubstitution::DoNothing   => text,
+    };
+
+    let text = converters::convert_entities(&text, &config.entities);
+
+    let quote_style = locale.quote_style();
+
+    let text = if uses_french_spacing(config, locale) {
+        spacing::apply_french_spacing(&text, &quote_style, &config.entities, &quote_context.prev_char)
+    } else {
+        text
+    };
+
+    converters::render_quotes(&text, &quote_style, &config.apostrophe, &config.entities)
+}
+
+fn handle_text_token(text: String, config: &SubstitutionConfig, locale: &Locale, quote_context: &mut QuoteContext, quote_stack: &mut QuoteStack, result: &mut Vec<String>, in_skipped_tag: bool) {
+
+    // Remember the last character of this token before processing.  If the
+    // token ends with an HTML entity, we remember the character it stands for.
+    let this_token_last_char = context::last_char(&text);
+
+    // Plain text is going to be embedded in HTML if we're producing
+    // HTML entities, so we need to escape any characters that would
+    // otherwise be read as markup.
+    let is_plain_text = matches!(config.input_format, InputFormat::PlainText);
+    let is_html_output = matches!(
+        config.entities,
+        EntitiesSubstitution::HtmlNumericEntities | EntitiesSubstitution::HtmlNamedEntities
+    );
 
     let processed_text = if in_skipped_tag {
         text
     } else {
-        let text = converters::process_escapes(&text);
-        let text = converters::convert_dashes(&text, config);
-
-        let text = match config.ellipses {
-            EllipsesSubstitution::ConvertToEntity => converters::convert_ellipses(&text),
-            EllipsesSubstitution::DoNothing       => text,
+        let text = match (is_plain_text, is_html_output) {
+            (true, true)  => converters::escape_html(&text),
+            (true, false) => converters::escape_ampersands(&text),
+            (false, _)    => text,
         };
 
-        // Note: backticks need to be processed before quotes, and double
-        // backticks need to be processed before single backticks.
+        let text = convert_text(&text, config, locale, quote_context, quote_stack, !is_plain_text || is_html_output);
 
-        let text = match config.double_backticks {
-            QuotesSubstitution::ConvertToCurly => converters::convert_double_backticks(&text),
-            QuotesSubstitution::DoNothing      => text,
-        };
+        if is_plain_text && !is_html_output {
+            converters::unescape_escapes(&text)
+        } else {
+            text
+        }
+    };
 
-        let text = match config.single_backticks {
-            QuotesSubstitution::ConvertToCurly => converters::convert_single_backticks(&text),
-            QuotesSubstitution::DoNothing      => text,
-        };
+    quote_context.prev_char = this_token_last_char;
+    result.push(processed_text);
+}
+
+fn handle_paragraph_break(contents: String, quote_context: &mut QuoteContext, quote_stack: &mut QuoteStack, result: &mut Vec<String>) {
+    // A paragraph break resets the quote context, so a quote at the start
+    // of the next paragraph is treated as if it came after whitespace,
+    // whatever the previous paragraph ended with.
+    quote_context.prev_char = contents.chars().last();
+    quote_stack.end_block();
+    result.push(contents);
+}
+
+/// Handle an opening or closing `<q>` tag.
+///
+/// Returns the curly quote to use in place of the tag, if we've been
+/// asked to replace them.
+fn handle_q_tag(tag: QTag, config: &SubstitutionConfig, locale: &Locale, q_stack: &mut Vec<OpenQ>, quote_context: &mut QuoteContext, quote_stack: &mut QuoteStack) -> Option<String> {
+    // Nested `<q>` elements alternate between primary and secondary
+    // quotes.  If we're tracking nesting, the quote stack will also take
+    // into account any quotes typed outside the `<q>`.
+    match tag {
+        QTag::Opening => {
+            q_stack.push(OpenQ {
+                style: locale.quote_style(),
+                uses_french_spacing: uses_french_spacing(config, locale),
+                at_start: true,
+                stripped_quote: None,
+            });
+
+            let depth = q_stack.len() - 1;
+            render_q_quote(nesting::opening_placeholder(depth), &q_stack[depth], config, quote_context, quote_stack)
+        },
+
+        // If there's no open `<q>`, this closing tag doesn't match
+        // anything, so we leave it as-is.
+        QTag::Closing => {
+            let open_q = q_stack.pop()?;
+            render_q_quote(nesting::closing_placeholder(q_stack.len()), &open_q, config, quote_context, quote_stack)
+        },
+    }
+}
+
+fn render_q_quote(placeholder: char, open_q: &OpenQ, config: &SubstitutionConfig, quote_context: &mut QuoteContext, quote_stack: &mut QuoteStack) -> Option<String> {
+    if !matches!(config.q_elements, QElementsSubstitution::ReplaceWithQuotes) {
+        return None;
+    }
+
+    let placeholder = quote_stack.apply(&placeholder.to_string(), config);
+    let quote = converters::render_quotes(&placeholder, &open_q.style, &config.apostrophe, &config.entities);
+
+    let glyph = placeholder.chars().next().and_then(|c| open_q.style.glyph_for(c));
+    quote_context.prev_char = glyph;
+
+    // The text inside the `<q>` doesn't know it's next to a guillemet,
+    // so we need to add the French spacing here.
+    let narrow_space = converters::render_glyph(entities::NARROW_NO_BREAK_SPACE, &config.entities);
+
+    Some(match glyph {
+        Some('«') if open_q.uses_french_spacing => format!("{}{}", quote, narrow_space),
+        Some('»') if open_q.uses_french_spacing => format!("{}{}", narrow_space, quote),
+        _ => quote,
+    })
+}
+
+lazy_static! {
+    // Matches the opening or closing tag of a block element, or a line
+    // break -- text either side of one of these doesn't run together.
+    static ref BLOCK_TAG_REGEX: Regex = Regex::new(
+        r"(?i)^</?(?P<tag_name>address|article|aside|blockquote|body|br|dd|details|dialog|div|dl|dt|fieldset|figcaption|figure|footer|form|h[1-6]|header|hr|li|main|nav|ol|p|section|summary|table|td|th|tr|ul)[\s/>]").unwrap();
+}
+
+/// Returns the first character of the text after each token, looking past
+/// any inline tags (e.g. `<em>` or `<a>`).  A block tag or a paragraph
+/// break counts as a newline, because the text doesn't carry on past it.
+fn following_chars(tokens: &[Token]) -> Vec<Option<char>> {
+    let mut result = vec![None; tokens.len()];
+    let mut next_char = None;
 
-        let text = match config.quote_chars {
-            QuotesSubstitution::ConvertToCurly => converters::convert_quotes(&text, &prev_token_last_char),
-            QuotesSubstitution::DoNothing      => text,
+    for (i, token) in tokens.iter().enumerate().rev() {
+        result[i] = next_char;
+
+        next_char = match token {
+            Token::Text(contents)           => context::first_char(contents),
+            Token::ParagraphBreak(contents) => contents.chars().next(),
+            Token::Tag(contents) if (*BLOCK_TAG_REGEX).is_match(contents) => Some('\n'),
+            Token::Tag(_)                   => next_char,
         };
+    }
 
-        let text = converters::convert_entities(&text, &config.entities);
+    result
+}
 
-        text
+/// Reset the quote context at the start or end of a block element, e.g.
+/// `<p>` or `</li>`, or at a line break.
+///
+/// Text in a new block doesn't follow on from the text before it, so a
+/// quote at the start of the block is treated as if it came at the start
+/// of a line, whatever the previous block ended with.  Apart from line
+/// breaks, this also ends any quotations that are still open -- although
+/// they might be continued in the next paragraph.
+fn handle_block_tag(contents: &str, quote_context: &mut QuoteContext, quote_stack: &mut QuoteStack) {
+    let tag_match = match (*BLOCK_TAG_REGEX).captures(contents) {
+        Some(m) => m,
+        None    => return,
     };
 
-    *prev_token_last_char = Some(this_token_last_char);
-    result.push(processed_text);
+    quote_context.prev_char = Some('\n');
+
+    if !tag_match["tag_name"].eq_ignore_ascii_case("br") {
+        quote_stack.end_block();
+    }
 }
 
 pub fn smartypants(text: &str, config: &SubstitutionConfig) -> String {
+    smarten(text, config, false).0
+}
+
+/// Like `smartypants`, but also returns warnings about problems in the
+/// input, e.g. quotations that are never closed.
+///
+/// Each warning includes the byte offset in `text` of the problem.
+pub fn smartypants_with_warnings(text: &str, config: &SubstitutionConfig) -> (String, Vec<Warning>) {
+    smarten(text, config, true)
+}
+
+/// Convert `text`, and collect the warnings if `collect_warnings` is true.
+///
+/// Working out the warnings means keeping track of every quotation in
+/// the document, so we skip it if nobody's going to read them.
+fn smarten(text: &str, config: &SubstitutionConfig, collect_warnings: bool) -> (String, Vec<Warning>) {
     let mut result: Vec<String> = vec![];
 
     // Records whether we're in any skipped tags where we don't
     // want to do text processing.
     let mut skipped_tag_stack: Vec<String> = vec![];
 
-    // This is a cheat, used to get some context for one-character tokens
-    // that consist of a single quote character.  We remember the last
-    // character of the previous text token, to use as context to curl
-    // single-character quote tokens correctly.
-    let mut prev_token_last_char: Option<char> = None;
+    // The quote converters only see one text token at a time, so we tell
+    // them about the characters either side of it, e.g. if a quote is
+    // right next to an inline tag.  We remember the last character of the
+    // previous text token, and look ahead for the first character of the
+    // next one.
+    let mut quote_context = QuoteContext::default();
+
+    // Records the elements with a `lang` attribute that we're inside,
+    // and the language they set.
+    let mut lang_stack: Vec<(String, Locale)> = vec![];
+
+    // Records the `<q>` elements that we're inside.
+    let mut q_stack: Vec<OpenQ> = vec![];
+
+    // Records the quotations that are open, so we can check they get
+    // closed, and where each token starts in the input.
+    let mut quote_stack = QuoteStack::new(config, collect_warnings);
+    let mut offset = 0;
+
+    // If we're converting ASCII guillemets, a `<<` is text rather than
+    // the start of a tag, and likewise `<-` and `<=` if we're converting
+    // arrows or operators, so we need to tell the tokenizer.
+    let tokens = match config.input_format {
+        InputFormat::Html => tokenize::tokenize_with_options(
+            text,
+            matches!(config.ascii_guillemets, GuillemetsSubstitution::ConvertToGuillemets),
+            matches!(config.arrows, SymbolsSubstitution::ConvertToSymbol)
+                || matches!(config.comparison_operators, SymbolsSubstitution::ConvertToSymbol),
+        ),
+        InputFormat::PlainText => tokenize::tokenize_plain_text(text),
+    };
+
+    let next_chars = following_chars(&tokens);
+    let mut tokens = tokens.into_iter().zip(next_chars).peekable();
+
+    while let Some((token, next_char)) = tokens.next() {
+        let token_len = match &token {
+            Token::Tag(contents) | Token::Text(contents) | Token::ParagraphBreak(contents) => contents.len(),
+        };
 
-    for token in tokenize::tokenize(text) {
         match token {
-            Token::Tag(contents) => handle_tag_token(contents, &mut result, &mut skipped_tag_stack),
+            Token::Tag(contents) => {
+                handle_block_tag(&contents, &mut quote_context, &mut quote_stack);
+                handle_lang_attribute(&contents, config, &mut lang_stack);
+                let locale = lang_stack.last().map_or(&config.locale, |(_, locale)| locale);
+
+                if let Some(open_q) = q_stack.last_mut() {
+                    open_q.at_start = false;
+                }
+
+                let q_tag = match config.q_elements {
+                    QElementsSubstitution::DoNothing => None,
+                    _ if !skipped_tag_stack.is_empty() => None,
+                    _ => q_elements::q_tag(&contents),
+                };
+
+                quote_stack.start_tag(offset);
+
+                match q_tag.and_then(|tag| handle_q_tag(tag, config, locale, &mut q_stack, &mut quote_context, &mut quote_stack)) {
+                    Some(quote) => result.push(quote),
+                    None        => handle_tag_token(contents, config, locale, &mut result, &mut skipped_tag_stack),
+                }
+            },
+            Token::ParagraphBreak(contents) => handle_paragraph_break(contents, &mut quote_context, &mut quote_stack, &mut result),
             Token::Text(contents) => {
                 let in_skipped_tag = !skipped_tag_stack.is_empty();
-                handle_text_token(contents, &config, &mut prev_token_last_char, &mut result, in_skipped_tag);
+                let locale = lang_stack.last().map_or(&config.locale, |(_, locale)| locale);
+
+                let is_before_q_close = matches!(
+                    tokens.peek(),
+                    Some((Token::Tag(next), _)) if q_elements::q_tag(next) == Some(QTag::Closing)
+                );
+                let (contents, stripped_len) = q_elements::strip_inner_quotes(contents, q_stack.last_mut(), is_before_q_close);
+
+                quote_stack.start_token(&contents, offset + stripped_len, config);
+                quote_context.next_char = next_char;
+                handle_text_token(contents, config, locale, &mut quote_context, &mut quote_stack, &mut result, in_skipped_tag);
             },
         }
+
+        offset += token_len;
     }
 
-    result.join("")
+    (result.join(""), quote_stack.into_warnings())
 }
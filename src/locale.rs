@@ -0,0 +1,253 @@
+// This file contains the language-specific conventions we know about.
+//
+// Different languages use different glyphs for quotation marks -- English
+// uses “…”, German uses „…“, French uses « … », Japanese uses 「…」, and
+// so on.  The quote converters only decide whether each quote opens or
+// closes a quotation; the locale decides which glyph that turns into.
+
+use crate::entities::{CLOSING_PRIMARY_QUOTE, CLOSING_SECONDARY_QUOTE, OPENING_PRIMARY_QUOTE, OPENING_SECONDARY_QUOTE};
+
+/// The glyphs used to open and close quotations in a language.
+///
+/// The primary quotes are used for a top-level quotation, and the
+/// secondary quotes for a quotation nested inside it.  In English, a
+/// `"` becomes a primary quote and a `'` becomes a secondary quote.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuoteStyle {
+    pub primary_opening: char,
+    pub primary_closing: char,
+    pub secondary_opening: char,
+    pub secondary_closing: char,
+}
+
+impl QuoteStyle {
+    const fn new(primary: (char, char), secondary: (char, char)) -> QuoteStyle {
+        QuoteStyle {
+            primary_opening: primary.0,
+            primary_closing: primary.1,
+            secondary_opening: secondary.0,
+            secondary_closing: secondary.1,
+        }
+    }
+
+    /// Returns the glyph that the quote placeholder `c` becomes in this
+    /// style, or `None` if `c` isn't a quote placeholder.
+    pub(crate) fn glyph_for(&self, c: char) -> Option<char> {
+        match c {
+            OPENING_PRIMARY_QUOTE   => Some(self.primary_opening),
+            CLOSING_PRIMARY_QUOTE   => Some(self.primary_closing),
+            OPENING_SECONDARY_QUOTE => Some(self.secondary_opening),
+            CLOSING_SECONDARY_QUOTE => Some(self.secondary_closing),
+            _                       => None,
+        }
+    }
+}
+
+/// “…” and ‘…’, e.g. American English, Dutch, Chinese
+const ENGLISH: QuoteStyle = QuoteStyle::new(('“', '”'), ('‘', '’'));
+
+/// ‘…’ and “…”, e.g. British English
+const BRITISH: QuoteStyle = QuoteStyle::new(('‘', '’'), ('“', '”'));
+
+/// „…“ and ‚…‘, e.g. German, Czech, Slovak
+const LOW_HIGH_REVERSED: QuoteStyle = QuoteStyle::new(('„', '“'), ('‚', '‘'));
+
+/// „…” and «…», e.g. Polish, Romanian
+const LOW_HIGH_GUILLEMETS: QuoteStyle = QuoteStyle::new(('„', '”'), ('«', '»'));
+
+/// „…” and »…«, e.g. Hungarian
+const LOW_HIGH_INWARD: QuoteStyle = QuoteStyle::new(('„', '”'), ('»', '«'));
+
+/// „…” and ‚…’, e.g. Croatian, Serbian
+const LOW_HIGH: QuoteStyle = QuoteStyle::new(('„', '”'), ('‚', '’'));
+
+/// «…» and “…”, e.g. French, Spanish, Italian, Portuguese
+const GUILLEMETS_ENGLISH: QuoteStyle = QuoteStyle::new(('«', '»'), ('“', '”'));
+
+/// «…» and „…“, e.g. Russian, Ukrainian
+const GUILLEMETS_LOW_HIGH: QuoteStyle = QuoteStyle::new(('«', '»'), ('„', '“'));
+
+/// «…» and ‹…›, e.g. Swiss German and Swiss French
+const GUILLEMETS_SINGLE: QuoteStyle = QuoteStyle::new(('«', '»'), ('‹', '›'));
+
+/// «…» and ‘…’, e.g. Norwegian
+const GUILLEMETS_ENGLISH_SINGLE: QuoteStyle = QuoteStyle::new(('«', '»'), ('‘', '’'));
+
+/// »…« and ›…‹, e.g. Danish
+const INWARD_GUILLEMETS: QuoteStyle = QuoteStyle::new(('»', '«'), ('›', '‹'));
+
+/// ”…” and ’…’, e.g. Swedish, Finnish
+const RIGHT_ONLY: QuoteStyle = QuoteStyle::new(('”', '”'), ('’', '’'));
+
+/// 「…」 and 『…』, e.g. Japanese, Traditional Chinese
+const CORNER_BRACKETS: QuoteStyle = QuoteStyle::new(('「', '」'), ('『', '』'));
+
+/// Words in English that start with an apostrophe, because the start of
+/// the word has been left out, e.g. `'tis` for "it is".
+///
+/// We use these for every language, because English words turn up in
+/// text in lots of other languages.
+const ENGLISH_ELISIONS: &[&str] = &[
+    "'bout", "'cause", "'cos", "'em", "'gainst", "'n'", "'nuff", "'round",
+    "'til", "'tis", "'tween", "'twas", "'twere", "'twill", "'twixt", "'twould",
+];
+
+/// Articles and other short words in French that drop their final vowel
+/// before a vowel, e.g. `l'ami` or `qu'il`.
+const FRENCH_ELISIONS: &[&str] = &[
+    "c'", "d'", "j'", "l'", "m'", "n'", "s'", "t'", "qu'",
+    "jusqu'", "lorsqu'", "puisqu'", "quoiqu'",
+];
+
+/// As with French, but Italian, e.g. `dell'arte` or `un'altra`.
+const ITALIAN_ELISIONS: &[&str] = &[
+    "l'", "c'", "un'", "dell'", "all'", "dall'", "nell'", "sull'", "quell'",
+    "quest'", "bell'", "sant'",
+];
+
+/// Words in Dutch that start with an apostrophe, e.g. `'s-Hertogenbosch`
+/// for "des Hertogen bosch", or `'t` for "het".
+const DUTCH_ELISIONS: &[&str] = &[
+    "'s", "'t", "'n", "'k",
+];
+
+/// A language, identified by a [BCP 47](https://www.rfc-editor.org/info/bcp47)
+/// tag such as `en`, `de-CH` or `zh-Hant-TW`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    tag: String,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::new("en")
+    }
+}
+
+impl Locale {
+    /// Create a locale from a BCP 47 tag.  Tags are case-insensitive, and
+    /// underscores are accepted in place of hyphens (e.g. `pt_BR`).
+    pub fn new(tag: &str) -> Locale {
+        Locale {
+            tag: tag.trim().replace('_', "-").to_lowercase(),
+        }
+    }
+
+    /// The full tag for this locale, in lowercase.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The primary language subtag, e.g. `de` for `de-CH`.
+    pub fn language(&self) -> &str {
+        self.tag.split('-').next().unwrap_or("")
+    }
+
+    /// Returns true if this tag has `subtag` after the language, e.g. a
+    /// region (`ch` in `de-ch`) or a script (`hant` in `zh-hant-tw`).
+    fn has_subtag(&self, subtag: &str) -> bool {
+        self.tag.split('-').skip(1).any(|s| s == subtag)
+    }
+
+    /// Returns true if this language puts a narrow space before `;`, `:`,
+    /// `!`, `?` and `»`, and after `«`.
+    ///
+    /// This is French, except in Switzerland, where it's usually left out.
+    pub fn uses_french_spacing(&self) -> bool {
+        self.language() == "fr" && !self.has_subtag("ch")
+    }
+
+    /// The quotation marks used in this language.
+    ///
+    /// Languages we don't know about get English quotes.
+    pub fn quote_style(&self) -> QuoteStyle {
+        match self.language() {
+            "en" if self.has_subtag("gb") => BRITISH,
+            "en"                          => ENGLISH,
+
+            "de" | "gsw" if self.has_subtag("ch") || self.has_subtag("li") => GUILLEMETS_SINGLE,
+            "fr" if self.has_subtag("ch")                                  => GUILLEMETS_SINGLE,
+
+            "de" | "cs" | "sk" | "sl" | "lt" | "et" | "is" | "ka" => LOW_HIGH_REVERSED,
+            "pl" | "ro"                                           => LOW_HIGH_GUILLEMETS,
+            "hu"                                                  => LOW_HIGH_INWARD,
+            "hr" | "bs" | "sr" | "bg"                             => LOW_HIGH,
+
+            "pt" if self.has_subtag("br") => ENGLISH,
+
+            "fr" | "es" | "ca" | "it" | "pt" | "el" | "eu" | "gl" => GUILLEMETS_ENGLISH,
+            "ru" | "uk" | "be"                                    => GUILLEMETS_LOW_HIGH,
+            "no" | "nb" | "nn"                                    => GUILLEMETS_ENGLISH_SINGLE,
+            "da"                                                  => INWARD_GUILLEMETS,
+            "sv" | "fi"                                           => RIGHT_ONLY,
+
+            "zh" if self.has_subtag("hant") || self.has_subtag("tw") || self.has_subtag("hk") => CORNER_BRACKETS,
+            "ja"                                                                          => CORNER_BRACKETS,
+
+            _ => ENGLISH,
+        }
+    }
+
+    /// Words in this language that contain an apostrophe at the start or
+    /// the end, where the apostrophe would otherwise be mistaken for a
+    /// quote, e.g. `'tis` or `l'`.
+    pub fn elisions(&self) -> Vec<&'static str> {
+        let elisions: &[&str] = match self.language() {
+            "fr" => FRENCH_ELISIONS,
+            "it" => ITALIAN_ELISIONS,
+            "nl" => DUTCH_ELISIONS,
+            _    => &[],
+        };
+
+        ENGLISH_ELISIONS.iter().chain(elisions).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::Locale;
+
+    fn quotes(tag: &str) -> String {
+        let style = Locale::new(tag).quote_style();
+        format!(
+            "{}{}{}{}",
+            style.primary_opening, style.primary_closing,
+            style.secondary_opening, style.secondary_closing
+        )
+    }
+
+    #[test]
+    fn it_picks_quotes_for_a_language() {
+        assert_eq!(quotes("en"), "“”‘’");
+        assert_eq!(quotes("de"), "„“‚‘");
+        assert_eq!(quotes("fr"), "«»“”");
+        assert_eq!(quotes("sv"), "””’’");
+        assert_eq!(quotes("pl"), "„”«»");
+        assert_eq!(quotes("da"), "»«›‹");
+        assert_eq!(quotes("ja"), "「」『』");
+    }
+
+    #[test]
+    fn it_picks_quotes_for_a_region_or_script() {
+        assert_eq!(quotes("en-GB"), "‘’“”");
+        assert_eq!(quotes("de_CH"), "«»‹›");
+        assert_eq!(quotes("pt-BR"), "“”‘’");
+        assert_eq!(quotes("pt-PT"), "«»“”");
+        assert_eq!(quotes("zh-Hant-TW"), "「」『』");
+        assert_eq!(quotes("zh-CN"), "“”‘’");
+    }
+
+    #[test]
+    fn it_falls_back_to_english_quotes() {
+        assert_eq!(quotes("tlh"), "“”‘’");
+        assert_eq!(quotes(""), "“”‘’");
+    }
+
+    #[test]
+    fn it_picks_elisions_for_a_language() {
+        assert!(Locale::new("en").elisions().contains(&"'tis"));
+        assert!(Locale::new("fr-CA").elisions().contains(&"qu'"));
+        assert!(Locale::new("fr-CA").elisions().contains(&"'tis"));
+        assert!(!Locale::new("en").elisions().contains(&"qu'"));
+    }
+}
@@ -0,0 +1,442 @@
+// This file contains the code for keeping track of which quotations are
+// open as we work through a document.
+//
+// The quote converters decide which way each quote curls by looking at
+// the characters either side of it.  That's usually right, but it can't
+// tell if a quotation has already been opened, so e.g. a quote that looks
+// like it opens a second quotation might really be closing the first one.
+//
+// Here we walk through the quote placeholders in order, keeping a stack
+// of the quotations that are open.  We use it to:
+//
+//  1.  Fix quotes that can't be right, e.g. a `'` that closes a quotation
+//      when there isn't one open is probably an apostrophe.
+//
+//  2.  Pick primary or secondary quotes by how deeply the quotation is
+//      nested, rather than by whether it was typed as `"` or `'`.
+//
+//  3.  Warn about quotations that are never opened or never closed.
+//
+// The stack lasts for a block element, e.g. a paragraph.  In fiction, a
+// quotation that continues into the next paragraph isn't closed at the
+// end of the first one, and the next paragraph starts with a fresh opening
+// quote -- so a quotation that's still open at the end of a block is only
+// reported as unclosed if the next block doesn't start by reopening it.
+
+use regex::Regex;
+
+use crate::config::{NormalizationSubstitution, QuoteNestingSubstitution, QuotesSubstitution, SubstitutionConfig};
+use crate::context;
+use crate::entities::*;
+use crate::warnings::{Warning, WarningKind};
+
+/// Whether a quotation was opened with a double quote or a single quote.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum QuoteKind {
+    Double,
+    Single,
+}
+
+#[derive(Default)]
+pub struct QuoteStack {
+    /// Whether we're keeping track of quotations at all.  We only need to
+    /// if we're tracking nesting, or someone's asked for the warnings.
+    is_active: bool,
+
+    /// The quotations that are currently open, and where they were opened.
+    open: Vec<(QuoteKind, usize)>,
+
+    /// The quotations that were open at the end of the last block, which
+    /// might be reopened at the start of this one, and how many of them
+    /// have been reopened so far.
+    carried: Vec<(QuoteKind, usize)>,
+    reopened: usize,
+
+    /// The quotes in the current token, in order, and how many of them
+    /// we've used so far.
+    quotes: Vec<QuoteSource>,
+    next_quote: usize,
+
+    /// The byte offset in the input of the current token.
+    token_offset: usize,
+
+    warnings: Vec<Warning>,
+}
+
+/// A character in the input that will become a quote placeholder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct QuoteSource {
+    /// The byte offset of the quote in the input.
+    offset: usize,
+
+    /// Whether it reaches the quote converters as a straight quote, in
+    /// which case it might become a prime instead.
+    is_straight: bool,
+}
+
+impl QuoteStack {
+    /// Create a stack for a document converted with `config`.
+    ///
+    /// If we're not tracking nesting and `collect_warnings` is false, the
+    /// stack doesn't do anything.
+    pub fn new(config: &SubstitutionConfig, collect_warnings: bool) -> QuoteStack {
+        QuoteStack {
+            is_active: collect_warnings
+                || matches!(config.quote_nesting, QuoteNestingSubstitution::TrackNesting),
+            ..QuoteStack::default()
+        }
+    }
+
+    /// Get ready to process a text token that starts at `offset` in the
+    /// input, so we can report where any quotes in it came from.
+    ///
+    /// The offsets are worked out from `text` as it appears in the input,
+    /// before any of the converters have changed it.
+    pub fn start_token(&mut self, text: &str, offset: usize, config: &SubstitutionConfig) {
+        if !self.is_active {
+            return;
+        }
+
+        self.quotes = find_quotes(text, config)
+            .into_iter()
+            .map(|q| QuoteSource { offset: q.offset + offset, ..q })
+            .collect();
+        self.next_quote = 0;
+        self.token_offset = offset;
+    }
+
+    /// Get ready to process a tag that starts at `offset` in the input.
+    pub fn start_tag(&mut self, offset: usize) {
+        self.quotes = vec![];
+        self.next_quote = 0;
+        self.token_offset = offset;
+    }
+
+    /// Forget the straight quotes in the current token that the quote
+    /// converters turned into primes, which don't get placeholders.
+    ///
+    /// `primes` are the indices of those quotes among the straight quotes
+    /// in the token, in order.
+    pub fn skip_primes(&mut self, primes: &[usize]) {
+        if !self.is_active || primes.is_empty() {
+            return;
+        }
+
+        let mut straight_quotes = 0;
+
+        self.quotes.retain(|q| {
+            if !q.is_straight {
+                return true;
+            }
+
+            let is_prime = primes.binary_search(&straight_quotes).is_ok();
+            straight_quotes += 1;
+            !is_prime
+        });
+    }
+
+    /// Update the stack with the quote placeholders in `text`.
+    ///
+    /// If we've been asked to track nesting, we also replace the
+    /// placeholders with the ones for the quote's depth.
+    pub fn apply(&mut self, text: &str, config: &SubstitutionConfig) -> String {
+        if !self.is_active {
+            return text.to_string();
+        }
+
+        let rewrite = matches!(config.quote_nesting, QuoteNestingSubstitution::TrackNesting);
+
+        let mut result = String::with_capacity(text.len());
+
+        for c in text.chars() {
+            if !self.carried.is_empty() {
+                if c.is_whitespace() {
+                    result.push(c);
+                    continue;
+                }
+
+                if let Some(reopened) = self.reopen(c) {
+                    result.push(if rewrite { reopened } else { c });
+                    continue;
+                }
+            }
+
+            let (kind, is_opening) = match c {
+                OPENING_PRIMARY_QUOTE   => (QuoteKind::Double, true),
+                CLOSING_PRIMARY_QUOTE   => (QuoteKind::Double, false),
+                OPENING_SECONDARY_QUOTE => (QuoteKind::Single, true),
+                CLOSING_SECONDARY_QUOTE => (QuoteKind::Single, false),
+                APOSTROPHE => {
+                    self.next_offset();
+                    result.push(c);
+                    continue;
+                },
+                _ => {
+                    result.push(c);
+                    continue;
+                },
+            };
+
+            let offset = self.next_offset();
+            let resolved = self.update(kind, is_opening, offset);
+
+            result.push(if rewrite { resolved } else { c });
+        }
+
+        result
+    }
+
+    /// Update the stack for a single quote, and return the placeholder
+    /// it should become.
+    fn update(&mut self, kind: QuoteKind, is_opening: bool, offset: usize) -> char {
+        if is_opening {
+            let depth = self.open.len();
+            self.open.push((kind, offset));
+            return opening_placeholder(depth);
+        }
+
+        match self.open.iter().rposition(|(k, _)| *k == kind) {
+            Some(depth) => {
+                // Anything opened inside this quotation and not closed
+                // is closed along with it.
+                for (_, offset) in self.open.drain(depth + 1..) {
+                    self.warnings.push(Warning { kind: WarningKind::UnclosedQuote, offset });
+                }
+                self.open.pop();
+                closing_placeholder(depth)
+            },
+
+            // A closing single quote with nothing to close is probably an
+            // apostrophe, e.g. "goin' home".
+            None if kind == QuoteKind::Single => APOSTROPHE,
+
+            None => {
+                self.warnings.push(Warning { kind: WarningKind::UnopenedQuote, offset });
+                CLOSING_PRIMARY_QUOTE
+            },
+        }
+    }
+
+    /// Handle the first thing in a block after a quotation was carried
+    /// over from the previous block.
+    ///
+    /// If `c` is an opening quote that reopens the next carried quotation,
+    /// returns the placeholder it should become.  Otherwise, the carried
+    /// quotations are either reopened (if we've seen some of them) or
+    /// reported as unclosed, and we carry on as normal.
+    fn reopen(&mut self, c: char) -> Option<char> {
+        let kind = match c {
+            OPENING_PRIMARY_QUOTE   => Some(QuoteKind::Double),
+            OPENING_SECONDARY_QUOTE => Some(QuoteKind::Single),
+            _                       => None,
+        };
+
+        let depth = self.reopened;
+
+        if kind.is_some() && kind == self.carried.get(depth).map(|(k, _)| *k) {
+            self.next_offset();
+            self.reopened += 1;
+
+            if self.reopened == self.carried.len() {
+                self.open = std::mem::take(&mut self.carried);
+                self.reopened = 0;
+            }
+
+            return Some(opening_placeholder(depth));
+        }
+
+        if self.reopened > 0 {
+            self.open = std::mem::take(&mut self.carried);
+        } else {
+            self.warn_unclosed_carried();
+        }
+        self.reopened = 0;
+
+        None
+    }
+
+    fn warn_unclosed_carried(&mut self) {
+        for (_, offset) in self.carried.drain(..) {
+            self.warnings.push(Warning { kind: WarningKind::UnclosedQuote, offset });
+        }
+    }
+
+    /// Returns the offset of the next quote in the current token.
+    fn next_offset(&mut self) -> usize {
+        let offset = self.quotes
+            .get(self.next_quote)
+            .or(self.quotes.last())
+            .map_or(self.token_offset, |q| q.offset);
+
+        self.next_quote += 1;
+        offset
+    }
+
+    /// Close the current block element.  Any quotations that are still
+    /// open are carried over, in case the next block reopens them.
+    pub fn end_block(&mut self) {
+        if self.open.is_empty() {
+            return;
+        }
+
+        self.warn_unclosed_carried();
+        self.carried = std::mem::take(&mut self.open);
+        self.reopened = 0;
+    }
+
+    /// Returns the warnings for the whole document, in the order they
+    /// appear in the input.
+    pub fn into_warnings(mut self) -> Vec<Warning> {
+        self.end_block();
+        self.warn_unclosed_carried();
+        self.warnings.sort_by_key(|w| w.offset);
+        self.warnings
+    }
+}
+
+pub fn opening_placeholder(depth: usize) -> char {
+    if depth.is_multiple_of(2) { OPENING_PRIMARY_QUOTE } else { OPENING_SECONDARY_QUOTE }
+}
+
+pub fn closing_placeholder(depth: usize) -> char {
+    if depth.is_multiple_of(2) { CLOSING_PRIMARY_QUOTE } else { CLOSING_SECONDARY_QUOTE }
+}
+
+/// Returns the characters in `text` that will become quote placeholders,
+/// in order.
+///
+/// This mirrors the converters that create placeholders, so the nth
+/// quote belongs to the nth placeholder.  The exception is straight quotes
+/// that become primes, which the quote converters tell us about later.
+fn find_quotes(text: &str, config: &SubstitutionConfig) -> Vec<QuoteSource> {
+    lazy_static! {
+        static ref QUOTE_SOURCE_RE: Regex = Regex::new(
+            r#"\\.|``|''|(?i:&(?:quot|#0*34|#x0*22|apos|#0*39|#x0*27);)|[`'"‘’‚‛“”„‟«»‹›′″]"#).unwrap();
+    }
+
+    let is_on = |s: &QuotesSubstitution| matches!(s, QuotesSubstitution::ConvertToCurly);
+
+    let quote_chars = is_on(&config.quote_chars);
+    let escaped_quotes = quote_chars && is_on(&config.escaped_quotes);
+    let double_backticks = is_on(&config.double_backticks);
+    let single_backticks = is_on(&config.single_backticks);
+    let normalize = quote_chars && matches!(config.normalization, NormalizationSubstitution::Normalize);
+
+    // A straight single quote is converted along with single backticks,
+    // if they're turned on; otherwise it's left for the quote converters.
+    let straight_quote = |c: char, offset: usize| match c {
+        '\'' if single_backticks => Some(QuoteSource { offset, is_straight: false }),
+        _ if quote_chars         => Some(QuoteSource { offset, is_straight: true }),
+        _                        => None,
+    };
+
+    let backtick = |offset: usize| QuoteSource { offset, is_straight: false };
+
+    let mut quotes = vec![];
+
+    for m in (*QUOTE_SOURCE_RE).find_iter(text) {
+        let (start, s) = (m.start(), m.as_str());
+
+        match s {
+            _ if s.starts_with('\\')       => (),
+            "``" | "''" if double_backticks => quotes.push(backtick(start)),
+            "``" | "`" if single_backticks  => quotes.extend((start..start + s.len()).map(backtick)),
+            "``" | "`"                      => (),
+            "''"                            => quotes.extend([start, start + 1].into_iter().filter_map(|i| straight_quote('\'', i))),
+            "'" | "\""                      => quotes.extend(straight_quote(s.chars().next().unwrap(), start)),
+
+            _ if s.starts_with('&') && escaped_quotes =>
+                quotes.extend(context::decode(s).and_then(|c| straight_quote(c, start))),
+            _ if s.starts_with('&') => (),
+
+            "′" | "″" if text[..start].ends_with(|c: char| c.is_ascii_digit()) => (),
+            _ if !normalize => (),
+            "‘" | "’" | "‚" | "‛" | "‹" | "›" | "′" => quotes.extend(straight_quote('\'', start)),
+            _                                       => quotes.extend(straight_quote('"', start)),
+        }
+    }
+
+    quotes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{QuoteNestingSubstitution, QuotesSubstitution, SubstitutionConfig, SubstitutionConfigHelpers};
+    use crate::entities::*;
+    use crate::nesting::{find_quotes, QuoteStack};
+    use crate::warnings::{Warning, WarningKind};
+
+    #[test]
+    fn it_finds_the_quotes_in_the_input() {
+        let offsets = |text: &str, config: &SubstitutionConfig| -> Vec<usize> {
+            find_quotes(text, config).iter().map(|q| q.offset).collect()
+        };
+
+        let config = SubstitutionConfig::default();
+        assert_eq!(offsets(r#"a "b" \'c ``d'' 'e'"#, &config), vec![2, 4, 10, 13, 16, 18]);
+
+        // Single backticks convert straight single quotes too, even if the
+        // quote converters are turned off.
+        let config = SubstitutionConfig::default()
+            .with_quote_chars(QuotesSubstitution::DoNothing)
+            .with_single_backticks(QuotesSubstitution::ConvertToCurly);
+        assert_eq!(offsets(r#"a "b" `c' ``d''"#, &config), vec![6, 8, 10, 13]);
+    }
+
+    #[test]
+    fn it_skips_quotes_that_become_primes() {
+        let config = SubstitutionConfig::default();
+        let mut stack = QuoteStack::new(&config, true);
+
+        stack.start_token(r#"5'10" and "x"#, 0, &config);
+        stack.skip_primes(&[0, 1]);
+        stack.apply(&format!("5&#8242;10&#8243; and {}x", OPENING_PRIMARY_QUOTE), &config);
+
+        assert_eq!(stack.into_warnings(), vec![
+            Warning { kind: WarningKind::UnclosedQuote, offset: 10 },
+        ]);
+    }
+
+    #[test]
+    fn it_does_nothing_unless_its_needed() {
+        let config = SubstitutionConfig::default();
+        let mut stack = QuoteStack::new(&config, false);
+
+        stack.start_token(r#"a" b"#, 0, &config);
+        stack.apply(&format!("a{} b", CLOSING_PRIMARY_QUOTE), &config);
+
+        assert_eq!(stack.into_warnings(), vec![]);
+    }
+
+    #[test]
+    fn it_alternates_quotes_by_depth() {
+        let config = SubstitutionConfig::default()
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting);
+        let mut stack = QuoteStack::new(&config, false);
+
+        let text: String = [
+            OPENING_SECONDARY_QUOTE, OPENING_PRIMARY_QUOTE, CLOSING_PRIMARY_QUOTE, CLOSING_SECONDARY_QUOTE,
+        ].iter().collect();
+        let expected: String = [
+            OPENING_PRIMARY_QUOTE, OPENING_SECONDARY_QUOTE, CLOSING_SECONDARY_QUOTE, CLOSING_PRIMARY_QUOTE,
+        ].iter().collect();
+
+        assert_eq!(stack.apply(&text, &config), expected);
+        assert_eq!(stack.into_warnings(), vec![]);
+    }
+
+    #[test]
+    fn it_warns_about_unbalanced_quotes() {
+        let config = SubstitutionConfig::default();
+        let mut stack = QuoteStack::new(&config, true);
+
+        stack.start_token(r#"a" b "c"#, 10, &config);
+        let text = format!("a{} b {}c", CLOSING_PRIMARY_QUOTE, OPENING_PRIMARY_QUOTE);
+        stack.apply(&text, &config);
+
+        assert_eq!(stack.into_warnings(), vec![
+            Warning { kind: WarningKind::UnopenedQuote, offset: 11 },
+            Warning { kind: WarningKind::UnclosedQuote, offset: 15 },
+        ]);
+    }
+}
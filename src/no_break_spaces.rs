@@ -0,0 +1,172 @@
+// This file contains the code for replacing spaces with no-break spaces,
+// to stop a line breaking somewhere it would be hard to read, e.g. between
+// `10` and `kg`, or between `Mr.` and `Smith`.
+//
+// Each rule looks for a space between two things that belong together.
+// We only replace spaces and tabs -- a newline the author typed is left
+// alone -- and the rules look at the characters either side of the text,
+// so e.g. `Mr. <b>Smith</b>` still gets a no-break space.
+
+use regex::{Captures, Regex};
+
+use crate::config::NoBreakSpaceRule;
+use crate::context;
+use crate::entities::*;
+use crate::locale::Locale;
+use crate::utils;
+
+/// A space that should be a no-break space.
+///
+/// `re` matches the thing before the space (`keep`) and the space itself,
+/// and `next` matches the start of the text after the space.  `can_follow`
+/// is called with the character before the match, if any.
+struct Rule {
+    re: Regex,
+    next: Regex,
+    can_follow: fn(&Captures, Option<char>) -> bool,
+}
+
+impl Rule {
+    fn new(re: &str, next: &str, can_follow: fn(&Captures, Option<char>) -> bool) -> Rule {
+        Rule {
+            re: Regex::new(re).unwrap(),
+            next: Regex::new(&format!("^(?:{})", next)).unwrap(),
+            can_follow,
+        }
+    }
+}
+
+lazy_static! {
+    static ref NUMBER_AND_UNIT: Rule = Rule::new(
+        r"(?P<keep>\d)(?P<space>[ \t]+)",
+        r"(?x)
+            (?:
+                mg|g|kg|t|nm|µm|mm|cm|m|km|ml|cl|dl|l|ms|s|min|h|km/h|mph
+                |Hz|kHz|MHz|GHz|B|kB|KB|MB|GB|TB|W|kW|MW|V|mAh
+                |px|pt|em|ft|lbs?|oz|°C|°F|°|%|‰|€|£|\$
+            )
+            (?:[^\w/]|$)
+        ",
+        |_, _| true
+    );
+
+    static ref ABBREVIATION_AND_NUMBER: Rule = Rule::new(
+        r"(?P<keep>(?P<abbreviation>(?i:p|pp|vol|no|nr|fig|ch|chap|sec|art|para)\.)|§|¶|№)(?P<space>[ \t]+)",
+        r"\d",
+        |caps, prev| caps.name("abbreviation").is_none() || !is_word_char_or(prev, '.')
+    );
+
+    static ref HONORIFIC_AND_NAME: Rule = Rule::new(
+        r"(?P<keep>(?:(?:Mr|Mrs|Ms|Mx|Dr|Prof|Rev|St|Fr|Hr|Mme|Mlle|Capt|Col|Gen|Lt|Sen)\.?|Sir|Dame|Lord|Lady|M\.))(?P<space>[ \t]+)",
+        r"\p{Lu}",
+        |_, prev| !is_word_char_or(prev, '.')
+    );
+
+    static ref CZECH_AND_SLOVAK_SHORT_WORD: Rule = Rule::new(
+        r"(?P<keep>(?i:[aiksuvzo]))(?P<space>[ \t]+)",
+        r"\S",
+        |_, prev| !is_word_char_or(prev, '-')
+    );
+
+    static ref POLISH_SHORT_WORD: Rule = Rule::new(
+        r"(?P<keep>(?i:[aiouwz]))(?P<space>[ \t]+)",
+        r"\S",
+        |_, prev| !is_word_char_or(prev, '-')
+    );
+
+    static ref BEFORE_ELLIPSIS: Rule = Rule::new(
+        r"(?P<keep>\S)(?P<space>[ \t]+)",
+        r"…|&#8230;|&hellip;|\.&#160;\.",
+        |_, _| true
+    );
+
+    static ref AFTER_EM_DASH: Rule = Rule::new(
+        r"(?P<keep>—|&#8212;|&mdash;)(?P<space>[ \t]+)",
+        r"\S",
+        |_, _| true
+    );
+}
+
+/// Returns true if `c` is a word character (as in `\w`) or `other`.
+fn is_word_char_or(c: Option<char>, other: char) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == other)
+}
+
+/// Replace the spaces in `text` that match any of `rules` with no-break
+/// spaces.
+///
+/// `prev_char` and `next_char` are the characters either side of `text`,
+/// if any.
+pub fn insert_no_break_spaces(text: &str, rules: &[NoBreakSpaceRule], locale: &Locale, prev_char: &Option<char>, next_char: &Option<char>) -> String {
+    if rules.is_empty() {
+        return text.to_string();
+    }
+
+    // Add the characters either side of the text, so the rules can see
+    // what's on the other side of a tag.
+    context::with_context_padding(text, prev_char, next_char, |padded| {
+        rules.iter().fold(padded.to_string(), |text, rule| {
+            let rule: &Rule = match rule {
+                NoBreakSpaceRule::NumberAndUnit         => &NUMBER_AND_UNIT,
+                NoBreakSpaceRule::AbbreviationAndNumber => &ABBREVIATION_AND_NUMBER,
+                NoBreakSpaceRule::HonorificAndName      => &HONORIFIC_AND_NAME,
+                NoBreakSpaceRule::BeforeEllipsis        => &BEFORE_ELLIPSIS,
+                NoBreakSpaceRule::AfterEmDash           => &AFTER_EM_DASH,
+                NoBreakSpaceRule::ShortWords => match locale.language() {
+                    "cs" | "sk" => &CZECH_AND_SLOVAK_SHORT_WORD,
+                    "pl"        => &POLISH_SHORT_WORD,
+                    _           => return text,
+                },
+            };
+
+            utils::replace_all_with_context(&text, &rule.re, |caps, before, after| {
+                if (rule.can_follow)(caps, before.chars().next_back()) && rule.next.is_match(after) {
+                    Some(format!("{}{}", &caps["keep"], NO_BREAK_SPACE_ENTITY))
+                } else {
+                    None
+                }
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::NoBreakSpaceRule;
+    use crate::locale::Locale;
+    use crate::no_break_spaces::insert_no_break_spaces;
+
+    fn convert(text: &str, rule: NoBreakSpaceRule, locale: &str) -> String {
+        insert_no_break_spaces(text, &[rule], &Locale::new(locale), &None, &None)
+    }
+
+    #[test]
+    fn it_binds_numbers_to_units() {
+        assert_eq!(convert("10 kg and 5 km/h", NoBreakSpaceRule::NumberAndUnit, "en"), "10&#160;kg and 5&#160;km/h");
+        assert_eq!(convert("10 kids and 5 m", NoBreakSpaceRule::NumberAndUnit, "en"), "10 kids and 5&#160;m");
+        assert_eq!(convert("5 min or 30 mph", NoBreakSpaceRule::NumberAndUnit, "en"), "5&#160;min or 30&#160;mph");
+    }
+
+    #[test]
+    fn it_binds_abbreviations_and_honorifics() {
+        assert_eq!(convert("see p. 42 and § 3", NoBreakSpaceRule::AbbreviationAndNumber, "en"), "see p.&#160;42 and §&#160;3");
+        assert_eq!(convert("Mr. Smith and Dr Jones", NoBreakSpaceRule::HonorificAndName, "en"), "Mr.&#160;Smith and Dr&#160;Jones");
+        assert_eq!(convert("Mr. and Mrs. Smith", NoBreakSpaceRule::HonorificAndName, "en"), "Mr. and Mrs.&#160;Smith");
+        assert_eq!(convert("app. 3 and x§ 3", NoBreakSpaceRule::AbbreviationAndNumber, "en"), "app. 3 and x§&#160;3");
+        assert_eq!(convert("HMr. Smith", NoBreakSpaceRule::HonorificAndName, "en"), "HMr. Smith");
+    }
+
+    #[test]
+    fn it_binds_short_words_in_some_languages() {
+        assert_eq!(convert("a v Praze", NoBreakSpaceRule::ShortWords, "cs"), "a&#160;v&#160;Praze");
+        assert_eq!(convert("i w domu", NoBreakSpaceRule::ShortWords, "pl"), "i&#160;w&#160;domu");
+        assert_eq!(convert("a v Praze", NoBreakSpaceRule::ShortWords, "en"), "a v Praze");
+    }
+
+    #[test]
+    fn it_looks_at_the_characters_around_the_text() {
+        let rules = [NoBreakSpaceRule::HonorificAndName];
+        let text = insert_no_break_spaces("Mr. ", &rules, &Locale::default(), &Some(' '), &Some('S'));
+        assert_eq!(text, "Mr.&#160;");
+    }
+}
@@ -0,0 +1,182 @@
+// This file contains the code for typesetting numbers: a minus sign in
+// `-5`, a multiplication sign in `1920 x 1080`, a plus-minus sign in
+// `+-0.5`, and an en dash in a range like `1912-2004`.
+//
+// A hyphen next to a number isn't always one of these -- it might be part
+// of a date (`2022-02-08`), a phone number (`555-1234`) or an identifier
+// (`COVID-19`, `ISBN 978-3-16`) -- so we only convert it when the numbers
+// either side look like they're meant to be read as numbers.  In practice
+// that means:
+//
+//   - a minus sign is a hyphen with a digit after it, and nothing that
+//     could be part of a word or number before it
+//   - a range is exactly two numbers joined by a hyphen, where the second
+//     is bigger than the first, and neither has a leading zero
+
+use fancy_regex::{Captures, Regex as FancyRegex};
+
+use crate::config::{DashesSubstitution, SubstitutionConfig, SymbolsSubstitution};
+use crate::entities::*;
+
+lazy_static! {
+    static ref PLUS_MINUS_RE: FancyRegex =
+        FancyRegex::new(r"(?<!\+)\+/?-(?!-)").unwrap();
+
+    static ref NUMBER_RANGE_RE: FancyRegex =
+        FancyRegex::new(r"(?<![\w.,\-+#/:])(?P<start>\d+(?:\.\d+)?)-(?P<end>\d+(?:\.\d+)?)(?![\d\-/:_]|[.,]\d)").unwrap();
+
+    static ref MINUS_SIGN_RE: FancyRegex =
+        FancyRegex::new(r"(?<![\w.,\-])-(?=\.?\d)").unwrap();
+
+    static ref MULTIPLICATION_RE: FancyRegex =
+        FancyRegex::new(r"(?<![\w.])\d+(?:\.\d+)?(?:\s?[xX]\s?\d+(?:\.\d+)?)+").unwrap();
+}
+
+/// Apply the number conversions that are turned on in `config`.
+///
+/// Note: this has to run after `convert_dashes`, so we don't mistake
+/// half of a `--` for a minus sign.
+pub fn convert_numbers(text: &str, config: &SubstitutionConfig) -> String {
+    let is_on = |s: &SymbolsSubstitution| matches!(s, SymbolsSubstitution::ConvertToSymbol);
+
+    // Note: the plus-minus sign has to be converted before minus signs,
+    // otherwise `+-5` would become `+−5`.
+    let text = if is_on(&config.plus_minus_sign) {
+        convert_plus_minus_signs(text)
+    } else {
+        text.to_string()
+    };
+
+    let text = match config.number_ranges {
+        DashesSubstitution::DoNothing => text,
+        DashesSubstitution::EnDash    => convert_number_ranges(&text, EN_DASH_ENTITY),
+        DashesSubstitution::EmDash    => convert_number_ranges(&text, EM_DASH_ENTITY),
+    };
+
+    let text = if is_on(&config.minus_sign) {
+        convert_minus_signs(&text)
+    } else {
+        text
+    };
+
+    if is_on(&config.multiplication_sign) {
+        convert_multiplication_signs(&text)
+    } else {
+        text
+    }
+}
+
+/// Converts `+-` and `+/-` in `text` into plus-minus signs.
+pub fn convert_plus_minus_signs(text: &str) -> String {
+    (*PLUS_MINUS_RE).replace_all(text, PLUS_MINUS_ENTITY).to_string()
+}
+
+/// Converts the hyphen in a range of numbers (e.g. `pp. 10-20`) into
+/// `dash`, which is an HTML entity.
+pub fn convert_number_ranges(text: &str, dash: &str) -> String {
+    (*NUMBER_RANGE_RE)
+        .replace_all(text, |caps: &Captures| {
+            let (start, end) = (&caps["start"], &caps["end"]);
+
+            if is_range(start, end) {
+                format!("{}{}{}", start, dash, end)
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string()
+}
+
+/// Converts a hyphen before a number (e.g. `-5`) into a minus sign.
+pub fn convert_minus_signs(text: &str) -> String {
+    (*MINUS_SIGN_RE).replace_all(text, MINUS_ENTITY).to_string()
+}
+
+/// Converts an `x` between two numbers (e.g. `3x4`) into a multiplication
+/// sign.
+///
+/// We skip anything that starts `0x`, which is probably a hex number.
+pub fn convert_multiplication_signs(text: &str) -> String {
+    (*MULTIPLICATION_RE)
+        .replace_all(text, |caps: &Captures| {
+            let m = &caps[0];
+
+            if m.starts_with("0x") || m.starts_with("0X") {
+                m.to_string()
+            } else {
+                m.replace(['x', 'X'], MULTIPLICATION_ENTITY)
+            }
+        })
+        .to_string()
+}
+
+/// Returns true if `start-end` looks like a range of numbers, rather than
+/// e.g. a date, a phone number or a score.
+fn is_range(start: &str, end: &str) -> bool {
+    let has_leading_zero = |s: &str| s.len() > 1 && s.starts_with('0') && !s.starts_with("0.");
+
+    if has_leading_zero(start) || end.starts_with('0') {
+        return false;
+    }
+
+    // e.g. 555-1234, but not a range of round numbers like 800-1200
+    if start.len() == 3 && end.len() == 4 && !(start.ends_with("00") && end.ends_with("00")) {
+        return false;
+    }
+
+    // An abbreviated range like 1990-95 takes the leading digits it's
+    // missing from the start of the range.
+    let end = if end.len() < start.len() && !start.contains('.') && !end.contains('.') {
+        format!("{}{}", &start[..start.len() - end.len()], end)
+    } else {
+        end.to_string()
+    };
+
+    match (start.parse::<f64>(), end.parse::<f64>()) {
+        (Ok(start), Ok(end)) => start < end,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::numbers::*;
+
+    #[test]
+    fn it_converts_number_ranges() {
+        assert_eq!(convert_number_ranges("pp. 10-20", "&#8211;"), "pp. 10&#8211;20");
+        assert_eq!(convert_number_ranges("1912-2004", "&#8211;"), "1912&#8211;2004");
+        assert_eq!(convert_number_ranges("1990-95", "&#8211;"), "1990&#8211;95");
+        assert_eq!(convert_number_ranges("1.5-2.5kg", "&#8211;"), "1.5&#8211;2.5kg");
+        assert_eq!(convert_number_ranges("800-1200 words", "&#8211;"), "800&#8211;1200 words");
+    }
+
+    #[test]
+    fn it_leaves_things_that_arent_ranges() {
+        for text in ["2022-02-08", "2022-02", "555-1234", "(555) 123-4567", "555-123-4567", "1-25-2022", "12-31-1999", "10-20-30", "1-2-3", "COVID-19", "ISBN 978-3-16", "won 3-2", "A4-5", "10:30-11"] {
+            assert_eq!(convert_number_ranges(text, "&#8211;"), text);
+        }
+    }
+
+    #[test]
+    fn it_converts_minus_signs() {
+        assert_eq!(convert_minus_signs("-5"), "&#8722;5");
+        assert_eq!(convert_minus_signs("x = (-0.5)"), "x = (&#8722;0.5)");
+        assert_eq!(convert_minus_signs("2022-02-08 and COVID-19"), "2022-02-08 and COVID-19");
+    }
+
+    #[test]
+    fn it_converts_multiplication_signs() {
+        assert_eq!(convert_multiplication_signs("3x4"), "3&#215;4");
+        assert_eq!(convert_multiplication_signs("1920 x 1080"), "1920 &#215; 1080");
+        assert_eq!(convert_multiplication_signs("2x3x4"), "2&#215;3&#215;4");
+        assert_eq!(convert_multiplication_signs("0x1F"), "0x1F");
+        assert_eq!(convert_multiplication_signs("a3x4 and 2x"), "a3x4 and 2x");
+    }
+
+    #[test]
+    fn it_converts_plus_minus_signs() {
+        assert_eq!(convert_plus_minus_signs("5 +- 0.2 and +/-3"), "5 &#177; 0.2 and &#177;3");
+        assert_eq!(convert_plus_minus_signs("C++-style"), "C++-style");
+    }
+}
@@ -0,0 +1,147 @@
+// This file contains the code for raising the suffixes of ordinal
+// numbers, e.g. `1st` to `1<sup>st</sup>`.
+//
+// The suffixes depend on the language:
+//
+//   - English uses `st`, `nd`, `rd` and `th`, e.g. `1st` or `23rd`
+//   - French uses `er` and `re` for first, and `e` for everything else,
+//     e.g. `1er` or `2e`
+//   - Spanish and Portuguese use the ordinal indicators `º` and `ª`,
+//     which are typed as `o` and `a`, e.g. `1o` or `1.a`
+//
+// We only convert a suffix that's right for the number, so e.g. `2th` or
+// `11st` are left alone, because they're probably something else.
+
+use regex::{Captures, Regex};
+
+use crate::config::EntitiesSubstitution;
+use crate::locale::Locale;
+
+lazy_static! {
+    static ref ENGLISH_ORDINAL_RE: Regex =
+        Regex::new(r"\b(?P<number>\d+)(?P<suffix>st|nd|rd|th)\b").unwrap();
+
+    static ref FRENCH_ORDINAL_RE: Regex =
+        Regex::new(r"\b(?P<number>\d+)(?P<suffix>ers?|res?|es?)\b").unwrap();
+
+    static ref SPANISH_ORDINAL_RE: Regex =
+        Regex::new(r"\b(?P<number>\d+)(?P<period>\.?)(?P<indicator>[oa])\b").unwrap();
+}
+
+const SUPERSCRIPT_LETTERS: &[(char, char)] = &[
+    ('a', 'ᵃ'),
+    ('d', 'ᵈ'),
+    ('e', 'ᵉ'),
+    ('h', 'ʰ'),
+    ('n', 'ⁿ'),
+    ('r', 'ʳ'),
+    ('s', 'ˢ'),
+    ('t', 'ᵗ'),
+];
+
+/// Raise the suffixes of the ordinal numbers in `text`.
+///
+/// If `allow_markup` is true, we wrap the suffix in `<sup>`; otherwise we
+/// use superscript letters, or leave the suffix alone in ASCII.
+pub fn convert_ordinals(text: &str, locale: &Locale, entities_substitution: &EntitiesSubstitution, allow_markup: bool) -> String {
+    match locale.language() {
+        "es" | "pt" => convert_ordinal_indicators(text),
+        "fr"        => raise_suffixes(text, &FRENCH_ORDINAL_RE, is_french_suffix, entities_substitution, allow_markup),
+        _           => raise_suffixes(text, &ENGLISH_ORDINAL_RE, is_english_suffix, entities_substitution, allow_markup),
+    }
+}
+
+fn raise_suffixes(text: &str, re: &Regex, is_valid: fn(u64, &str) -> bool, entities_substitution: &EntitiesSubstitution, allow_markup: bool) -> String {
+    re.replace_all(text, |caps: &Captures| {
+        let (number, suffix) = (&caps["number"], &caps["suffix"]);
+
+        match number.parse::<u64>() {
+            Ok(n) if is_valid(n, suffix) =>
+                format!("{}{}", number, raise(suffix, entities_substitution, allow_markup)),
+            _ => caps[0].to_string(),
+        }
+    })
+    .to_string()
+}
+
+/// Converts `o` and `a` after a number in `text` into the ordinal
+/// indicators `º` and `ª`, e.g. `1o` to `1º`.
+fn convert_ordinal_indicators(text: &str) -> String {
+    (*SPANISH_ORDINAL_RE)
+        .replace_all(text, |caps: &Captures| {
+            let indicator = if &caps["indicator"] == "o" { 'º' } else { 'ª' };
+            format!("{}{}&#{};", &caps["number"], &caps["period"], indicator as u32)
+        })
+        .to_string()
+}
+
+fn raise(suffix: &str, entities_substitution: &EntitiesSubstitution, allow_markup: bool) -> String {
+    if allow_markup {
+        return format!("<sup>{}</sup>", suffix);
+    }
+
+    let superscript = suffix.chars().map(|c| {
+        SUPERSCRIPT_LETTERS
+            .iter()
+            .find(|(letter, _)| *letter == c)
+            .map_or(c, |(_, raised)| *raised)
+    });
+
+    match entities_substitution {
+        EntitiesSubstitution::AsciiEquivalents  => suffix.to_string(),
+        EntitiesSubstitution::UnicodeCharacters => superscript.collect(),
+        _                                       => superscript.map(|c| format!("&#{};", c as u32)).collect(),
+    }
+}
+
+fn is_english_suffix(n: u64, suffix: &str) -> bool {
+    let expected = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _)       => "st",
+        (2, _)       => "nd",
+        (3, _)       => "rd",
+        _            => "th",
+    };
+
+    suffix == expected
+}
+
+fn is_french_suffix(n: u64, suffix: &str) -> bool {
+    match suffix {
+        "er" | "ers" | "re" | "res" => n == 1,
+        _                           => n > 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::EntitiesSubstitution;
+    use crate::locale::Locale;
+    use crate::ordinals::convert_ordinals;
+
+    #[test]
+    fn it_raises_english_suffixes() {
+        let (locale, unicode) = (Locale::default(), EntitiesSubstitution::UnicodeCharacters);
+
+        assert_eq!(
+            convert_ordinals("1st, 2nd, 23rd, 4th, 11th, 112th", &locale, &unicode, true),
+            "1<sup>st</sup>, 2<sup>nd</sup>, 23<sup>rd</sup>, 4<sup>th</sup>, 11<sup>th</sup>, 112<sup>th</sup>",
+        );
+        assert_eq!(convert_ordinals("1st and 2nd", &locale, &unicode, false), "1ˢᵗ and 2ⁿᵈ");
+        assert_eq!(convert_ordinals("2th, 11st, 1stly, a1st", &locale, &unicode, true), "2th, 11st, 1stly, a1st");
+    }
+
+    #[test]
+    fn it_raises_french_suffixes() {
+        let (locale, unicode) = (Locale::new("fr"), EntitiesSubstitution::UnicodeCharacters);
+
+        assert_eq!(convert_ordinals("1er, 1re, 2e, 3es, 1e", &locale, &unicode, false), "1ᵉʳ, 1ʳᵉ, 2ᵉ, 3ᵉˢ, 1e");
+    }
+
+    #[test]
+    fn it_uses_ordinal_indicators_in_spanish() {
+        let (locale, unicode) = (Locale::new("es"), EntitiesSubstitution::UnicodeCharacters);
+
+        assert_eq!(convert_ordinals("1o, 1.a, 2º", &locale, &unicode, true), "1&#186;, 1.&#170;, 2º");
+    }
+}
@@ -0,0 +1,132 @@
+// This file contains the code for handling `<q>` elements.
+//
+// Browsers add quotation marks around a `<q>` element with CSS, but they
+// don't always pick the right ones for the language, and authors often
+// type their own quotes inside the element too, so the reader sees two
+// sets of quotes.  We can either remove the quotes typed inside the `<q>`,
+// or replace the `<q>` tags with the right curly quotes ourselves.
+
+use regex::Regex;
+
+use crate::context;
+use crate::locale::QuoteStyle;
+
+lazy_static! {
+    static ref Q_TAG_RE: Regex =
+        Regex::new(r"(?i)^<(?P<closing_slash>/)?q[\s/>]").unwrap();
+
+    static ref LEADING_UNIT_RE: Regex =
+        Regex::new(r"^(?:&#?[[:alnum:]]+;|.)").unwrap();
+
+    static ref TRAILING_UNIT_RE: Regex =
+        Regex::new(r"(?:&#?[[:alnum:]]+;|.)$").unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QTag {
+    Opening,
+    Closing,
+}
+
+/// A `<q>` element we're inside.
+pub struct OpenQ {
+    /// The quotes for the language of the element.  The closing quote
+    /// uses the same style as the opening quote, even if the language
+    /// has changed by the time we see the `</q>`.
+    pub style: QuoteStyle,
+    pub uses_french_spacing: bool,
+
+    /// Whether we haven't seen anything inside the element yet.
+    pub at_start: bool,
+
+    /// The straight quote we removed from the start of the element, if
+    /// any.  We only remove the same quote from the end.
+    pub stripped_quote: Option<char>,
+}
+
+/// Returns whether `contents` is an opening or closing `<q>` tag.
+pub fn q_tag(contents: &str) -> Option<QTag> {
+    (*Q_TAG_RE).captures(contents).map(|m| {
+        if m.name("closing_slash").is_some() { QTag::Closing } else { QTag::Opening }
+    })
+}
+
+/// Remove any straight quotes typed just inside a `<q>` element.
+///
+/// `open_q` is the element we're inside, if any, and `is_before_close`
+/// is true if this text comes right before the `</q>`.  Returns the text
+/// and the number of bytes removed from the start.
+pub fn strip_inner_quotes(text: String, open_q: Option<&mut OpenQ>, is_before_close: bool) -> (String, usize) {
+    let open_q = match open_q {
+        Some(q) => q,
+        None    => return (text, 0),
+    };
+
+    let mut start = 0;
+
+    if open_q.at_start {
+        open_q.at_start = false;
+
+        if let Some(m) = (*LEADING_UNIT_RE).find(&text) {
+            if let Some(quote) = context::decode(m.as_str()).filter(|c| is_straight_quote(*c)) {
+                open_q.stripped_quote = Some(quote);
+                start = m.end();
+            }
+        }
+    }
+
+    let mut end = text.len();
+
+    if is_before_close {
+        if let Some(m) = (*TRAILING_UNIT_RE).find(&text[start..]) {
+            if open_q.stripped_quote.is_some() && context::decode(m.as_str()) == open_q.stripped_quote {
+                end = start + m.start();
+            }
+        }
+    }
+
+    (text[start..end].to_string(), start)
+}
+
+fn is_straight_quote(c: char) -> bool {
+    c == '"' || c == '\''
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::Locale;
+    use crate::q_elements::{q_tag, strip_inner_quotes, OpenQ, QTag};
+
+    fn open_q() -> OpenQ {
+        OpenQ {
+            style: Locale::default().quote_style(),
+            uses_french_spacing: false,
+            at_start: true,
+            stripped_quote: None,
+        }
+    }
+
+    #[test]
+    fn it_recognises_q_tags() {
+        assert_eq!(q_tag("<q>"), Some(QTag::Opening));
+        assert_eq!(q_tag("<Q cite=\"x\">"), Some(QTag::Opening));
+        assert_eq!(q_tag("</q>"), Some(QTag::Closing));
+        assert_eq!(q_tag("<qa>"), None);
+        assert_eq!(q_tag("<p>"), None);
+    }
+
+    #[test]
+    fn it_strips_matching_quotes() {
+        let text = String::from("\"Hello\"");
+        assert_eq!(strip_inner_quotes(text, Some(&mut open_q()), true), (String::from("Hello"), 1));
+
+        let text = String::from("&quot;Hello&#34;");
+        assert_eq!(strip_inner_quotes(text, Some(&mut open_q()), true), (String::from("Hello"), 6));
+
+        let text = String::from("Goin' home'");
+        assert_eq!(strip_inner_quotes(text, Some(&mut open_q()), true), (String::from("Goin' home'"), 0));
+
+        let text = String::from("\"Hello\"");
+        assert_eq!(strip_inner_quotes(text, None, true), (String::from("\"Hello\""), 0));
+    }
+}
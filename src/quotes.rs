@@ -1,42 +1,169 @@
 // This file contains the code for converting normal quotes (`"` and `'`)
-// into HTML curly quote entities.
+// into curly quotes.
+//
+// The functions here only decide whether each quote opens or closes a
+// quotation (or is an apostrophe), and mark it with one of the quote
+// placeholders from `entities.rs`.  The placeholders are turned into the
+// right glyphs for the locale afterwards.
 //
 // This logic is more complicated than the other converters, and I found
 // it useful to break it up into smaller functions to keep the converters
 // file relatively simple.
 
+use fancy_regex::{Captures, Regex as FancyRegex};
 use regex::Regex;
-use fancy_regex::{Regex as FancyRegex};
+use std::collections::BTreeSet;
+use std::ops::Range;
 
+use crate::context;
 use crate::entities::*;
 use crate::utils::*;
 
-/// Handle the special case of a single-character ' token.
+// The quote regexes below capture the thing immediately before a quote
+// as `prefix`, which is either an HTML entity or a single character.
+// We then decode it to decide which way the quote should curl, so that
+// e.g. `&nbsp;`, `&#160;` and a literal U+00A0 all behave the same way.
+lazy_static! {
+    static ref OPENING_SINGLE_QUOTE_RE: FancyRegex =
+        create_re(r#"(?s)(?P<prefix>&#?[[:alnum:]]+;|--|.)'(?=[[:word:]])"#);
+
+    static ref CLOSING_SINGLE_QUOTE_RE: FancyRegex =
+        create_re(r#"(?s)(?P<prefix>&#?[[:alnum:]]+;|.)'"#);
+
+    static ref OPENING_DOUBLE_QUOTE_RE: FancyRegex =
+        create_re(r#"(?s)(?P<prefix>&#?[[:alnum:]]+;|--|.)"(?=[[:word:]])"#);
+
+    static ref CLOSING_DOUBLE_QUOTE_RE: FancyRegex =
+        create_re(r#"(?s)(?P<prefix>&#?[[:alnum:]]+;|.)?""#);
+}
+
+/// Returns the byte offsets of the quotes in `text` that are really
+/// primes, e.g. the `'` and `"` in `5'10"` or `40°26'46"N`.
 ///
-/// If the last character of the previous token was whitespace,
-/// then this is an opening quote, otherwise it's a closing quote.
+/// A quote can only be a prime if it comes straight after a digit.  Then
+/// it's a prime if:
 ///
-/// e.g. if the previous token was "hello ", then we'd do "hello‘",
-/// whereas if the previous token was "isn", then we'd do "isn’".
+///   * it's part of a pair like `5'10"` or `2' 30"`, or
+///   * it comes after degrees or minutes, e.g. the `'` in `40°26'`, or
+///   * it isn't closing a quotation, e.g. the `"` in `a 12" pizza`, but
+///     not the one in `"Take 5"`
 ///
-pub fn handle_single_straight_quote_token(prev_token_last_char: &Option<char>) -> String {
-    if is_whitespace(prev_token_last_char) {
-        OPENING_SINGLE_CURLY_QUOTE_ENTITY.to_string()
-    } else {
-        CLOSING_SINGLE_CURLY_QUOTE_ENTITY.to_string()
+pub fn find_primes(text: &str) -> Vec<usize> {
+    lazy_static! {
+        static ref UNIT_RE: Regex = Regex::new(r"(?s)&#?[[:alnum:]]+;|.").unwrap();
+
+        static ref NUMBER_RE: Regex = Regex::new(r"^\s*\d+(?:\.\d+)?").unwrap();
+    }
+
+    let units: Vec<(usize, Option<char>)> = (*UNIT_RE)
+        .find_iter(text)
+        .map(|m| (m.start(), context::decode(m.as_str())))
+        .collect();
+
+    let char_at = |i: usize| units.get(i).and_then(|(_, c)| *c);
+    let is_digit = |i: usize| char_at(i).is_some_and(|c| c.is_ascii_digit());
+
+    let mut primes: BTreeSet<usize> = BTreeSet::new();
+
+    // How many double and single quotations are open
+    let mut depth = [0usize; 2];
+
+    for i in 0..units.len() {
+        let kind = match char_at(i) {
+            Some('"')  => 0,
+            Some('\'') => 1,
+            _          => continue,
+        };
+
+        if primes.contains(&i) {
+            continue;
+        }
+
+        if i > 0 && is_digit(i - 1) {
+            let mut start = i - 1;
+            while start > 0 && (is_digit(start - 1) || char_at(start - 1) == Some('.')) {
+                start -= 1;
+            }
+
+            let mut before = start;
+            while before > 0 && char_at(before - 1).is_some_and(char::is_whitespace) {
+                before -= 1;
+            }
+            let is_after_degrees = before > 0
+                && (matches!(char_at(before - 1), Some('°' | '′')) || primes.contains(&(before - 1)));
+
+            // e.g. the `10"` in `5'10"`
+            let pair = if kind == 1 {
+                let rest = &text[units.get(i + 1).map_or(text.len(), |(offset, _)| *offset)..];
+                (*NUMBER_RE).find(rest)
+                    .map(|m| units[i + 1].0 + m.end())
+                    .filter(|offset| text[*offset..].starts_with('"'))
+                    .and_then(|offset| units.iter().position(|(o, _)| *o == offset))
+            } else {
+                None
+            };
+
+            if pair.is_some() || is_after_degrees || depth[kind] == 0 {
+                primes.insert(i);
+                primes.extend(pair);
+                continue;
+            }
+        }
+
+        // Keep track of whether we're inside a quotation, so we know if
+        // a quote after a number might be closing it.
+        let prev = if i > 0 { char_at(i - 1) } else { None };
+        let next = char_at(i + 1);
+
+        let is_opening = prev.is_none_or(context::is_opening_context)
+            && next.is_some_and(|c| !c.is_whitespace() && (kind == 0 || !c.is_ascii_digit()));
+        let is_apostrophe = kind == 1
+            && prev.is_some_and(char::is_alphanumeric)
+            && next.is_some_and(char::is_alphanumeric);
+
+        if is_opening {
+            depth[kind] += 1;
+        } else if !is_apostrophe {
+            depth[kind] = depth[kind].saturating_sub(1);
+        }
     }
+
+    primes.iter().map(|i| units[*i].0).collect()
 }
 
-/// Handle the special case of a single-character " token.
+/// Handle quotes after digits which are really primes, e.g. "5'10\"".
 ///
-/// We apply the same logic as `handle_single_straight_quote_token`, but
-/// with the HTML entities for double quotes.
-pub fn handle_double_straight_quote_token(prev_token_last_char: &Option<char>) -> String {
-    if is_whitespace(prev_token_last_char) {
-        OPENING_DOUBLE_CURLY_QUOTE_ENTITY.to_string()
-    } else {
-        CLOSING_DOUBLE_CURLY_QUOTE_ENTITY.to_string()
+/// Only the quotes in `range` are converted; anything outside it is
+/// only used as context.  We also return which of the quotes in `range`
+/// became primes, counting from 0, so the quote stack can skip them.
+pub fn handle_primes(text: &str, range: Range<usize>) -> (String, Vec<usize>) {
+    let primes: BTreeSet<usize> = find_primes(text)
+        .into_iter()
+        .filter(|i| range.contains(i))
+        .collect();
+
+    let mut result = String::with_capacity(text.len());
+    let mut converted = vec![];
+    let mut quote_count = 0;
+
+    for (i, c) in text.char_indices() {
+        let is_prime = primes.contains(&i);
+
+        match c {
+            '\'' if is_prime => result.push_str(PRIME_ENTITY),
+            '"'  if is_prime => result.push_str(DOUBLE_PRIME_ENTITY),
+            _                => result.push(c),
+        }
+
+        if matches!(c, '\'' | '"') && range.contains(&i) {
+            if is_prime {
+                converted.push(quote_count);
+            }
+            quote_count += 1;
+        }
     }
+
+    (result, converted)
 }
 
 /// Handle the case where the first character is a quote, followed by
@@ -55,8 +182,8 @@ pub fn handle_leading_quote_with_punctuation(text: &str) -> String {
             FancyRegex::new(r#"^"(?=[[[:punct:]]]\B)"#).unwrap();
     }
 
-    let text = (*FIRST_SINGLE_QUOTE_RE).replace(&text, CLOSING_SINGLE_CURLY_QUOTE_ENTITY);
-    let text = (*FIRST_DOUBLE_QUOTE_RE).replace(&text, CLOSING_DOUBLE_CURLY_QUOTE_ENTITY);
+    let text = (*FIRST_SINGLE_QUOTE_RE).replace(text, CLOSING_SECONDARY_QUOTE.to_string());
+    let text = (*FIRST_DOUBLE_QUOTE_RE).replace(&text, CLOSING_PRIMARY_QUOTE.to_string());
 
     text.to_string()
 }
@@ -65,28 +192,34 @@ pub fn handle_leading_quote_with_punctuation(text: &str) -> String {
 ///
 /// <p>They said, "'Quoted' words in a larger quote."</p>
 ///
-pub fn handle_double_sets_of_quotes(text: &str) -> String {
-
-    // Note: as above, the regex in the Perl/Python implementations uses
-    // lookahead assertions, but we can't use them in Rust.
+/// We only do this where a quotation can open, i.e. after whitespace, a
+/// dash or an opening quote, or at the start of the document if
+/// `is_start` is true.  Otherwise `"'s` in `<em>Hello</em>"'s` would open
+/// two quotations.
+pub fn handle_double_sets_of_quotes(text: &str, is_start: bool) -> String {
     lazy_static! {
-        static ref DOUBLE_THEN_SINGLE_QUOTE: FancyRegex =
-            FancyRegex::new(r#""'(?=[[:word:]])"#).unwrap();
+        static ref DOUBLE_THEN_SINGLE_QUOTE: Regex = Regex::new(r#""'"#).unwrap();
 
-        static ref SINGLE_THEN_DOUBLE_QUOTE: FancyRegex =
-            FancyRegex::new(r#"'"(?=[[:word:]])"#).unwrap();
+        static ref SINGLE_THEN_DOUBLE_QUOTE: Regex = Regex::new(r#"'""#).unwrap();
     }
 
-    let text = (*DOUBLE_THEN_SINGLE_QUOTE).replace(
-        &text,
-        format!("{}{}", OPENING_DOUBLE_CURLY_QUOTE_ENTITY, OPENING_SINGLE_CURLY_QUOTE_ENTITY)
-    );
-    let text = (*SINGLE_THEN_DOUBLE_QUOTE).replace(
-        &text,
-        format!("{}{}", OPENING_SINGLE_CURLY_QUOTE_ENTITY, OPENING_DOUBLE_CURLY_QUOTE_ENTITY)
-    );
+    let can_open = |before: &str, after: &str| {
+        let is_opening = if before.is_empty() {
+            is_start
+        } else {
+            before.ends_with("--") || context::last_char(before).is_some_and(context::is_opening_context)
+        };
 
-    text.to_string()
+        is_opening && after.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    let text = replace_all_with_context(text, &DOUBLE_THEN_SINGLE_QUOTE, |_, before, after| {
+        can_open(before, after).then(|| format!("{}{}", OPENING_PRIMARY_QUOTE, OPENING_SECONDARY_QUOTE))
+    });
+
+    replace_all_with_context(&text, &SINGLE_THEN_DOUBLE_QUOTE, |_, before, after| {
+        can_open(before, after).then(|| format!("{}{}", OPENING_SECONDARY_QUOTE, OPENING_PRIMARY_QUOTE))
+    })
 }
 
 /// Handle decade abbreviations, e.g. "the '80s"
@@ -103,37 +236,61 @@ pub fn handle_decade_abbreviations(text: &str) -> String {
             create_re(r#"'(?=\d{2}s)"#);
     }
 
-    let text = (*DECADE_RE).replace(&text, CLOSING_SINGLE_CURLY_QUOTE_ENTITY);
+    let text = (*DECADE_RE).replace_all(text, APOSTROPHE.to_string());
 
     text.to_string()
 }
 
-pub fn handle_opening_single_quotes(text: &str) -> String {
+/// Handle words with an apostrophe at the start or the end, e.g. "'tis"
+/// or "l'ami", where the apostrophe would otherwise become a quote.
+///
+/// `elisions` is the list of words we know about, with a straight
+/// apostrophe, e.g. `'tis` or `l'`.  The comparison is case-insensitive.
+pub fn handle_elisions(text: &str, elisions: &[&str]) -> String {
 
+    // A word starting with an apostrophe (and maybe ending with one,
+    // e.g. "rock 'n' roll"), or a word ending with an apostrophe.
     lazy_static! {
-
-        // [[:space:]]  a whitespace char, or
-        // &nbsp;       a non-breaking space entity, or
-        // --           dashes, or
-        // &[mn]dash;   named dash entities, or
-        //              en/em dash decimal entities, or
-        //              en/em dash hex entities
-        //
-        // '            the quote
-        //
-        // [[:word:]]   followed by a word character\
-        //
-        static ref OPENING_SINGLE_QUOTE_RE: FancyRegex =
-            FancyRegex::new(&format!(
-                r#"(?P<prefix>[[:space:]]|&nbsp;|--|&[mn]dash;|{}|{}|{}|{};)'(?=[[:word:]])"#,
-                EN_DASH_ENTITY,
-                EM_DASH_ENTITY,
-                EN_DASH_HEX_ENTITY,
-                EM_DASH_HEX_ENTITY
-            )).unwrap();
+        static ref ELISION_RE: FancyRegex =
+            create_re(r#"(?<!\w)(?:'\w+'?(?!\w)|\w+')"#);
     }
 
-    let text = (*OPENING_SINGLE_QUOTE_RE).replace(&text, format!("$prefix{}", OPENING_SINGLE_CURLY_QUOTE_ENTITY));
+    let is_elision = |word: &str| {
+        let word = word.to_lowercase();
+        elisions.iter().any(|e| e.replace('’', "'").to_lowercase() == word)
+    };
+
+    let text = (*ELISION_RE).replace_all(text, |cap: &Captures| {
+        let word = &cap[0];
+
+        // If this is a word wrapped in quotes, e.g. 'tis', the closing
+        // quote might not be part of the elision.
+        let without_closing_quote = word
+            .strip_suffix('\'')
+            .filter(|w| w.len() > 1 && w.starts_with('\''));
+
+        let (elision, rest) = if is_elision(word) {
+            (word, "")
+        } else if without_closing_quote.is_some_and(is_elision) {
+            word.split_at(word.len() - 1)
+        } else {
+            return word.to_string();
+        };
+
+        format!("{}{}", elision.replace('\'', &APOSTROPHE.to_string()), rest)
+    });
+
+    text.to_string()
+}
+
+pub fn handle_opening_single_quotes(text: &str) -> String {
+    let text = (*OPENING_SINGLE_QUOTE_RE).replace_all(text, |cap: &Captures| {
+        if is_opening_prefix(&cap["prefix"]) {
+            format!("{}{}", &cap["prefix"], OPENING_SECONDARY_QUOTE)
+        } else {
+            cap[0].to_string()
+        }
+    });
 
     text.to_string()
 }
@@ -141,25 +298,43 @@ pub fn handle_opening_single_quotes(text: &str) -> String {
 pub fn handle_closing_single_quotes(text: &str) -> String {
 
     lazy_static! {
-        // If a single quote is preceded by anything which isn't whitespace
-        // or a parenthetical, then it's a closing quote.
-        static ref CLOSING_SINGLE_QUOTE_RE_1: FancyRegex =
-            create_re(r#"(?x)
-                (?P<close_class>[^ \t\r\n\[\{\(\-])
-                '
-            "#);
-
-        // If a single quote is followed by a letter, or an 's' at a word
-        // ending position, then it's a closing quote.
+        // If a single quote is followed by an 's' at a word ending position,
+        // then it's an apostrophe.
         //
         // This is a special case to handle something like
         // "<i>Custer</i>'s Last Stand.".
+        static ref POSSESSIVE_RE: FancyRegex =
+            create_re(r#"'(?=s\b)"#);
+
+        // If a single quote is followed by whitespace, then it's a
+        // closing quote.
         static ref CLOSING_SINGLE_QUOTE_RE_2: FancyRegex =
-            create_re(r#"'(?=\s|s\b)"#);
+            create_re(r#"'(?=\s)"#);
     }
 
-    let text = (*CLOSING_SINGLE_QUOTE_RE_1).replace(&text, format!("$close_class{CLOSING_SINGLE_CURLY_QUOTE_ENTITY}"));
-    let text = (*CLOSING_SINGLE_QUOTE_RE_2).replace(&text, CLOSING_SINGLE_CURLY_QUOTE_ENTITY);
+    // If a single quote is preceded by anything which isn't whitespace,
+    // a dash or a parenthetical, then it's a closing quote -- or if it's
+    // between two letters, an apostrophe.
+    let text = (*CLOSING_SINGLE_QUOTE_RE).replace_all(text, |cap: &Captures| {
+        let prefix = &cap["prefix"];
+
+        if !is_closing_prefix(prefix) {
+            return cap[0].to_string();
+        }
+
+        let is_followed_by_letter = text[cap.get(0).unwrap().end()..]
+            .chars()
+            .next()
+            .is_some_and(char::is_alphanumeric);
+
+        if is_followed_by_letter && prefix.chars().all(char::is_alphanumeric) {
+            format!("{}{}", prefix, APOSTROPHE)
+        } else {
+            format!("{}{}", prefix, CLOSING_SECONDARY_QUOTE)
+        }
+    });
+    let text = (*POSSESSIVE_RE).replace_all(&text, APOSTROPHE.to_string());
+    let text = (*CLOSING_SINGLE_QUOTE_RE_2).replace_all(&text, CLOSING_SECONDARY_QUOTE.to_string());
 
     text.to_string()
 }
@@ -169,47 +344,39 @@ pub fn handle_closing_single_quotes(text: &str) -> String {
 ///
 /// At this point, any remaining single quotes should be opening ones.
 pub fn handle_remaining_single_quotes(text: &str) -> String {
-    text.replace("'", OPENING_SINGLE_CURLY_QUOTE_ENTITY)
+    text.replace('\'', &OPENING_SECONDARY_QUOTE.to_string())
 }
 
 pub fn handle_opening_double_quotes(text: &str) -> String {
-    lazy_static! {
-
-        // [[:space:]]  a whitespace char, or
-        // &nbsp;       a non-breaking space entity, or
-        // --           dashes, or
-        // &[mn]dash;   named dash entities, or
-        //              en/em dash decimal entities, or
-        //              en/em dash hex entities
-        //
-        // '            the quote
-        //
-        // [[:word:]]   followed by a word character
-        //
-        static ref OPENING_DOUBLE_QUOTE_RE: FancyRegex =
-            FancyRegex::new(&format!(
-                r#"(?P<prefix>[[:space:]]|&nbsp;|--|&[mn]dash;|{}|{}|{}|{})"(?=[[:word:]])"#,
-                EN_DASH_ENTITY,
-                EM_DASH_ENTITY,
-                EN_DASH_HEX_ENTITY,
-                EM_DASH_HEX_ENTITY
-            )).unwrap();
-    }
-
-    let text = (*OPENING_DOUBLE_QUOTE_RE).replace(
-        &text, format!("$prefix{}", OPENING_DOUBLE_CURLY_QUOTE_ENTITY)
-    );
+    let text = (*OPENING_DOUBLE_QUOTE_RE).replace_all(text, |cap: &Captures| {
+        if is_opening_prefix(&cap["prefix"]) {
+            format!("{}{}", &cap["prefix"], OPENING_PRIMARY_QUOTE)
+        } else {
+            cap[0].to_string()
+        }
+    });
 
     text.to_string()
 }
 
 pub fn handle_closing_double_quotes(text: &str) -> String {
-    lazy_static! {
-        static ref CLOSING_DOUBLE_QUOTE_RE: FancyRegex =
-            FancyRegex::new(&format!(r#"(?P<close_class>{})?"((?P=close_class)|(?=[[:space:]])|$)"#, r#"[^\ \t\r\n\[\{\(\-]"#)).unwrap();
-    }
 
-    let text = (*CLOSING_DOUBLE_QUOTE_RE).replace(&text, format!("$close_class{}", CLOSING_DOUBLE_CURLY_QUOTE_ENTITY));
+    // A double quote is a closing quote if it's preceded by anything
+    // which isn't whitespace, a dash or a parenthetical, or if it's
+    // followed by whitespace or the end of the text.
+    let text = (*CLOSING_DOUBLE_QUOTE_RE).replace_all(text, |cap: &Captures| {
+        let prefix = cap.name("prefix").map_or("", |p| p.as_str());
+        let is_followed_by_space = text[cap.get(0).unwrap().end()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace());
+
+        if (!prefix.is_empty() && is_closing_prefix(prefix)) || is_followed_by_space {
+            format!("{}{}", prefix, CLOSING_PRIMARY_QUOTE)
+        } else {
+            cap[0].to_string()
+        }
+    });
 
     text.to_string()
 }
@@ -219,13 +386,18 @@ pub fn handle_closing_double_quotes(text: &str) -> String {
 ///
 /// At this point, any remaining double quotes should be opening ones.
 pub fn handle_remaining_double_quotes(text: &str) -> String {
-    text.replace("\"", OPENING_DOUBLE_CURLY_QUOTE_ENTITY)
+    text.replace('"', &OPENING_PRIMARY_QUOTE.to_string())
 }
 
-/// Returns true if `c` is whitespace, false otherwise
-fn is_whitespace(maybe_char: &Option<char>) -> bool {
-    match maybe_char {
-        Some(c) => Regex::new(r"\s").unwrap().is_match(&c.to_string()),
-        _       => false,
-    }
+/// Returns true if `prefix` means the quote after it is an opening quote.
+///
+/// The prefix is a single character, an HTML entity, or a double dash
+/// (which will become a dash if dashes are being converted).
+fn is_opening_prefix(prefix: &str) -> bool {
+    prefix == "--" || context::decode(prefix).is_some_and(context::is_opening_context)
+}
+
+/// Returns true if `prefix` means the quote after it can be a closing quote.
+fn is_closing_prefix(prefix: &str) -> bool {
+    context::decode(prefix).is_none_or(context::is_closing_context)
 }
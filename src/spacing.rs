@@ -0,0 +1,201 @@
+// This file contains the code for adjusting the spacing around punctuation.
+//
+// French typography puts a narrow no-break space (U+202F) before `;`, `:`,
+// `!`, `?` and `»`, and after `«`.  Authors type these with a normal space,
+// a no-break space, or no space at all, so we replace whatever's there with
+// the right space.
+//
+// We have to be careful not to add spaces to things which aren't really
+// punctuation in a sentence, e.g. URLs (`https://`), times (`10:30`) or
+// emoticons (`:-)`).  The rule is that we only add a space before `;`, `:`,
+// `!` or `?` if it's followed by whitespace, closing punctuation, or the
+// end of the text.
+//
+// We also adjust the spacing around em and en dashes, which varies between
+// style guides, e.g. `word—word` or `word — word`.
+
+use regex::{Captures, Regex};
+
+use crate::config::{DashSpacing, EntitiesSubstitution, SubstitutionConfig};
+use crate::context;
+use crate::converters::render_glyph;
+use crate::entities::*;
+use crate::locale::QuoteStyle;
+
+lazy_static! {
+    // A single unit of text: either an HTML entity, or a single character.
+    static ref UNIT_RE: Regex = Regex::new(r"(?s)&#?[[:alnum:]]+;|.").unwrap();
+
+    // An em or en dash, with any spaces within the line either side.
+    static ref DASH_RE: Regex = Regex::new(
+        r"(?P<before>(?:[ \t\u{a0}\u{2009}\u{200a}\u{202f}]|&(?:nbsp|thinsp|hairsp|#160|#8201|#8202|#8239);)*)(?P<dash>[—–]|&[mn]dash;|&#821[12];|(?i:&#x201[34];))(?P<after>(?:[ \t\u{a0}\u{2009}\u{200a}\u{202f}]|&(?:nbsp|thinsp|hairsp|#160|#8201|#8202|#8239);)*)"
+    ).unwrap();
+}
+
+/// Replace the spaces either side of the em and en dashes in `text` with
+/// the spacing from `config`.
+///
+/// We don't change the spacing at the start or end of a line, e.g. a dash
+/// that introduces the speaker of a quotation.  `prev_char` and
+/// `next_char` are the characters either side of `text`, if any.
+pub fn apply_dash_spacing(text: &str, config: &SubstitutionConfig, prev_char: &Option<char>, next_char: &Option<char>) -> String {
+    if matches!((&config.em_dash_spacing, &config.en_dash_spacing), (DashSpacing::DoNothing, DashSpacing::DoNothing)) {
+        return text.to_string();
+    }
+
+    // Add the characters either side of the text, so we can tell if a
+    // dash is at the start or end of a line.
+    context::with_context_padding(text, prev_char, next_char, |padded| {
+        (*DASH_RE).replace_all(padded, |caps: &Captures| {
+            let m = caps.get(0).unwrap();
+            let (before, dash, after) = (&caps["before"], &caps["dash"], &caps["after"]);
+
+            let is_em_dash = context::decode(dash) == Some('—');
+
+            // A closed-up en dash is probably in a range, e.g. `1912–2004`,
+            // so we leave it alone.
+            if !is_em_dash && before.is_empty() && after.is_empty() {
+                return m.as_str().to_string();
+            }
+
+            let space = match if is_em_dash { &config.em_dash_spacing } else { &config.en_dash_spacing } {
+                DashSpacing::DoNothing         => return m.as_str().to_string(),
+                DashSpacing::Closed            => "",
+                DashSpacing::Spaced            => " ",
+                DashSpacing::HairSpaces        => HAIR_SPACE_ENTITY,
+                DashSpacing::ThinNoBreakSpaces => NARROW_NO_BREAK_SPACE_ENTITY,
+            };
+
+            let is_in_line = |c: Option<char>| c.is_some_and(|c| !c.is_whitespace());
+
+            let before = if is_in_line(padded[..m.start()].chars().next_back()) { space } else { before };
+            let after = if is_in_line(padded[m.end()..].chars().next()) { space } else { after };
+
+            format!("{}{}{}", before, dash, after)
+        })
+        .to_string()
+    })
+}
+
+/// Add French-style narrow no-break spaces around punctuation in `text`.
+///
+/// This runs before the quote placeholders are rendered, so `style` tells
+/// us which of them will become guillemets.  `prev_char` is the character
+/// before `text`, if any, e.g. the end of the word in `<b>word</b>!`.
+pub fn apply_french_spacing(text: &str, style: &QuoteStyle, entities_substitution: &EntitiesSubstitution, prev_char: &Option<char>) -> String {
+    let narrow_space = render_glyph(NARROW_NO_BREAK_SPACE, entities_substitution);
+
+    let units: Vec<&str> = (*UNIT_RE).find_iter(text).map(|m| m.as_str()).collect();
+    let chars: Vec<Option<char>> = units.iter().map(|u| decode_unit(u, style)).collect();
+
+    let mut result: Vec<String> = vec![];
+    let mut prev_chars: Vec<Option<char>> = vec![];
+    let mut is_after_opening_guillemet = false;
+
+    for (i, unit) in units.iter().enumerate() {
+        let c = chars[i];
+
+        // Skip any spaces after an opening guillemet, then add a narrow
+        // space before the first thing that isn't a space.
+        if is_after_opening_guillemet {
+            if c.is_some_and(is_horizontal_space) {
+                continue;
+            }
+            result.push(narrow_space.clone());
+            prev_chars.push(Some(NARROW_NO_BREAK_SPACE));
+            is_after_opening_guillemet = false;
+        }
+
+        if let Some(c) = c.filter(|&c| needs_space_before(c, chars.get(i + 1).copied().flatten())) {
+
+            // Look past any spaces the author typed, to find the thing
+            // this punctuation is attached to.
+            let spaces = prev_chars
+                .iter()
+                .rev()
+                .take_while(|p| p.is_some_and(is_horizontal_space))
+                .count();
+            let prev = match prev_chars.len().checked_sub(spaces + 1) {
+                Some(j)             => prev_chars[j],
+
+                // If the punctuation is right at the start of the text,
+                // it's attached to whatever came before the text.
+                None if spaces == 0 => *prev_char,
+                None                => None,
+            };
+
+            let should_add_space = match prev {
+                None                         => spaces > 0,
+                Some(p) if p.is_whitespace() => false,
+                Some('(' | '[')              => false,
+                Some(p) if is_stacked(p, c)  => false,
+                Some(_)                      => true,
+            };
+
+            if should_add_space {
+                for _ in 0..spaces {
+                    result.pop();
+                    prev_chars.pop();
+                }
+                result.push(narrow_space.clone());
+                prev_chars.push(Some(NARROW_NO_BREAK_SPACE));
+            }
+        }
+
+        if c == Some('«') {
+            is_after_opening_guillemet = true;
+        }
+
+        result.push(unit.to_string());
+        prev_chars.push(c);
+    }
+
+    if is_after_opening_guillemet {
+        result.push(narrow_space);
+    }
+
+    result.join("")
+}
+
+/// Returns the character represented by `unit`, turning quote placeholders
+/// into the glyph they'll become.
+fn decode_unit(unit: &str, style: &QuoteStyle) -> Option<char> {
+    context::decode(unit).map(|c| style.glyph_for(c).unwrap_or(c))
+}
+
+/// Returns true if `c` is a space within a line, e.g. a normal space or
+/// a no-break space (but not a newline).
+fn is_horizontal_space(c: char) -> bool {
+    c.is_whitespace() && c != '\n' && c != '\r'
+}
+
+/// Returns true if `c` is the second of two stacked punctuation marks,
+/// e.g. the `!` in `?!`, which doesn't get a space between them.
+fn is_stacked(prev: char, c: char) -> bool {
+    matches!(prev, ';' | ':' | '!' | '?') && matches!(c, ';' | ':' | '!' | '?')
+}
+
+/// Returns true if `c` should get a narrow space before it, given that
+/// it's followed by `next`.
+fn needs_space_before(c: char, next: Option<char>) -> bool {
+    match c {
+        '»'       => true,
+        '!' | '?' => is_end_of_phrase(next, true),
+        ';' | ':' => is_end_of_phrase(next, false),
+        _         => false,
+    }
+}
+
+/// Returns true if `next` marks the end of a phrase, i.e. it's the end of
+/// the text, whitespace, or closing punctuation.
+///
+/// A closing parenthesis only counts if `allow_parenthesis` is true --
+/// after a colon or a semicolon, it's probably an emoticon, e.g. `:)`.
+fn is_end_of_phrase(next: Option<char>, allow_parenthesis: bool) -> bool {
+    match next {
+        None                         => true,
+        Some(n) if n.is_whitespace() => true,
+        Some(')')                    => allow_parenthesis,
+        Some(n)                      => matches!(n, '»' | '›' | '!' | '?' | '.' | ',' | ']' | '"' | '”' | '’' | APOSTROPHE),
+    }
+}
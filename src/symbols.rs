@@ -0,0 +1,209 @@
+// This file contains the code for converting ASCII stand-ins for symbols,
+// e.g. `(c)` or `(tm)`, into the symbols themselves.
+//
+// These are the substitutions most typographer tools make beyond the
+// SmartyPants core -- see the `numbers` module for `+/-`, and the ellipses
+// converter for `...`.
+//
+// A `(c)` might be a copyright symbol, or the third item in a list.  We
+// can tell when it comes straight after `(b)`, but not in general, which
+// is why these are all off by default.
+//
+// Arrows and comparison operators need a bit more care, because they're
+// made of `<`, `>` and `-`:
+//
+//   - In HTML, `<` and `>` are usually written as `&lt;` and `&gt;`, so we
+//     look for those as well.  The tokenizer also treats `<-` and `<=` as
+//     text rather than the start of a tag when these are turned on.
+//   - A long arrow like `-->` contains a double dash, so we convert arrows
+//     before dashes.
+//   - The arrow pass skips an HTML comment (`<!-- … -->`) that ends up in
+//     a text token, so its `-->` doesn't become an arrow.  (The tokenizer
+//     only does that to a comment with `--` inside it.)  The other passes
+//     don't skip it, so e.g. the dashes in it are still converted.
+
+use regex::{Captures, Regex};
+
+use crate::config::{SubstitutionConfig, SymbolsSubstitution};
+use crate::entities::*;
+use crate::utils;
+
+lazy_static! {
+    // A `(c)` straight after `(b)` is the third item in a list, not a
+    // copyright symbol, so we match the `(b)` as well and leave it alone.
+    static ref COPYRIGHT_RE: Regex = Regex::new(r"(?i)(?P<list>\(b\)\s*)?\(c\)").unwrap();
+
+    static ref REGISTERED_RE: Regex = Regex::new(r"(?i)\(r\)").unwrap();
+
+    static ref TRADEMARK_RE: Regex = Regex::new(r"(?i)\(tm\)").unwrap();
+
+    static ref SOUND_RECORDING_RE: Regex = Regex::new(r"(?i)\(p\)").unwrap();
+
+    static ref CARE_OF_RE: Regex = Regex::new(r"(?i)\bc/o\b").unwrap();
+
+    // The characters either side of an arrow or operator are checked in
+    // `can_convert`, e.g. so we don't turn the `<-` in `<--- x` into an
+    // arrow.
+    static ref ARROW_RE: Regex = Regex::new(r"(?x)
+        <!--.*?-->                  # an HTML comment, which we skip
+        | (?:<|&lt;)--?(?:>|&gt;)   # <-> or <-->
+        | (?:<|&lt;)=(?:>|&gt;)     # <=>
+        | (?:<|&lt;)--?             # <- or <--
+        | --?(?:>|&gt;)             # -> or -->
+        | =(?:>|&gt;)               # =>
+        | (?:<|&lt;)=               # <=
+        | (?:>|&gt;)=               # >=
+        | !=                        # !=
+    ").unwrap();
+}
+
+/// Apply the symbol conversions that are turned on in `config`.
+pub fn convert_symbols(text: &str, config: &SubstitutionConfig) -> String {
+    let substitutions = [
+        (&config.copyright_symbol,       &*COPYRIGHT_RE,       COPYRIGHT_ENTITY),
+        (&config.registered_symbol,      &*REGISTERED_RE,      REGISTERED_ENTITY),
+        (&config.trademark_symbol,       &*TRADEMARK_RE,       TRADEMARK_ENTITY),
+        (&config.sound_recording_symbol, &*SOUND_RECORDING_RE, SOUND_RECORDING_ENTITY),
+        (&config.care_of_symbol,         &*CARE_OF_RE,         CARE_OF_ENTITY),
+    ];
+
+    substitutions
+        .iter()
+        .filter(|(s, _, _)| matches!(s, SymbolsSubstitution::ConvertToSymbol))
+        .fold(text.to_string(), |text, (_, re, entity)| {
+            re.replace_all(&text, |caps: &Captures| {
+                match caps.name("list") {
+                    Some(_) => caps[0].to_string(),
+                    None    => entity.to_string(),
+                }
+            })
+            .to_string()
+        })
+}
+
+/// Convert the arrows and comparison operators in `text` that are
+/// turned on in `config`.
+///
+/// Note: this has to run before `convert_dashes`, otherwise the `--`
+/// in `-->` would become a dash.
+pub fn convert_arrows(text: &str, config: &SubstitutionConfig) -> String {
+    let is_on = |s: &SymbolsSubstitution| matches!(s, SymbolsSubstitution::ConvertToSymbol);
+
+    let arrows = is_on(&config.arrows);
+    let operators = is_on(&config.comparison_operators);
+
+    if !arrows && !operators {
+        return text.to_string();
+    }
+
+    utils::replace_all_with_context(text, &ARROW_RE, |caps, before, after| {
+        let m = &caps[0];
+
+        let symbol = m
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("--", "-");
+
+        if !can_convert(&symbol, before.chars().next_back(), after.chars().next()) {
+            return None;
+        }
+
+        let entity = match symbol.as_str() {
+            "<-"  if arrows    => LEFTWARDS_ARROW_ENTITY,
+            "->"  if arrows    => RIGHTWARDS_ARROW_ENTITY,
+            "<->" if arrows    => LEFT_RIGHT_ARROW_ENTITY,
+            "=>"  if arrows    => RIGHTWARDS_DOUBLE_ARROW_ENTITY,
+            "<=>" if arrows    => LEFT_RIGHT_DOUBLE_ARROW_ENTITY,
+            "<="  if operators => LESS_THAN_OR_EQUAL_ENTITY,
+            ">="  if operators => GREATER_THAN_OR_EQUAL_ENTITY,
+            "!="  if operators => NOT_EQUAL_ENTITY,
+            _                  => m,
+        };
+
+        Some(entity.to_string())
+    })
+}
+
+/// Returns true if `symbol` can be converted when it comes between
+/// `before` and `after`, i.e. it isn't part of a longer run of arrow
+/// or operator characters, like `<---` or `!==`.
+fn can_convert(symbol: &str, before: Option<char>, after: Option<char>) -> bool {
+    let is_before = |chars: &str| before.is_some_and(|c| chars.contains(c));
+    let is_after = |chars: &str| after.is_some_and(|c| chars.contains(c));
+
+    match symbol {
+        "<-" => !is_before("<!") && !is_after("-"),
+        "->" => !is_before("-"),
+        "=>" => !is_before("=<>!"),
+        "<=" => !is_before("<") && !is_after("="),
+        ">=" => !is_before(">=") && !is_after("="),
+        "!=" => !is_before("!=") && !is_after("="),
+        _    => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{SubstitutionConfig, SubstitutionConfigHelpers, SymbolsSubstitution};
+    use crate::symbols::{convert_arrows, convert_symbols};
+
+    #[test]
+    fn it_only_converts_the_symbols_that_are_turned_on() {
+        let text = "(c) 2024 Acme(R), c/o Widgets(TM) (p)";
+
+        assert_eq!(convert_symbols(text, &SubstitutionConfig::default()), text);
+
+        let config = SubstitutionConfig::default()
+            .with_copyright_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_trademark_symbol(SymbolsSubstitution::ConvertToSymbol);
+        assert_eq!(convert_symbols(text, &config), "&#169; 2024 Acme(R), c/o Widgets&#8482; (p)");
+    }
+
+    #[test]
+    fn it_skips_the_third_item_in_a_list() {
+        let config = SubstitutionConfig::default()
+            .with_copyright_symbol(SymbolsSubstitution::ConvertToSymbol);
+
+        assert_eq!(convert_symbols("items (a) (b) (c), (B)(C) and (c) 2024", &config), "items (a) (b) (c), (B)(C) and &#169; 2024");
+    }
+
+    #[test]
+    fn it_only_converts_care_of_as_a_word() {
+        let config = SubstitutionConfig::default()
+            .with_care_of_symbol(SymbolsSubstitution::ConvertToSymbol);
+
+        assert_eq!(convert_symbols("C/O Jane, disc/out", &config), "&#8453; Jane, disc/out");
+    }
+
+    #[test]
+    fn it_converts_arrows_and_operators() {
+        let config = SubstitutionConfig::default()
+            .with_arrows(SymbolsSubstitution::ConvertToSymbol)
+            .with_comparison_operators(SymbolsSubstitution::ConvertToSymbol);
+
+        assert_eq!(
+            convert_arrows("a -> b <- c <-> d => e <=> f --> g", &config),
+            "a &#8594; b &#8592; c &#8596; d &#8658; e &#8660; f &#8594; g",
+        );
+        assert_eq!(
+            convert_arrows("a &lt;= b &gt;= c != d -&gt; e", &config),
+            "a &#8804; b &#8805; c &#8800; d &#8594; e",
+        );
+    }
+
+    #[test]
+    fn it_leaves_comments_and_code_operators_alone() {
+        let config = SubstitutionConfig::default()
+            .with_arrows(SymbolsSubstitution::ConvertToSymbol)
+            .with_comparison_operators(SymbolsSubstitution::ConvertToSymbol);
+
+        for text in ["<!-- a -- b -->", "a !== b", "a <<= b", "a >== b", "a ==> b", "a ---> b", "a <--- b"] {
+            assert_eq!(convert_arrows(text, &config), text);
+        }
+
+        let config = SubstitutionConfig::default()
+            .with_arrows(SymbolsSubstitution::ConvertToSymbol);
+
+        assert_eq!(convert_arrows("a <= b -> c", &config), "a <= b &#8594; c");
+    }
+}
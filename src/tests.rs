@@ -1,7 +1,9 @@
 #![allow(warnings)]
 
-use crate::smartypants;
-use crate::config::{SubstitutionConfig, DashesSubstitution, EllipsesSubstitution, EntitiesSubstitution, QuotesSubstitution, SubstitutionConfigHelpers};
+use crate::{smartypants, smartypants_with_warnings};
+use crate::locale::Locale;
+use crate::warnings::{Warning, WarningKind};
+use crate::config::{SubstitutionConfig, ApostropheSubstitution, DashSpacing, DashesSubstitution, EllipsesOptions, EllipsesSubstitution, EllipsisSpaceBinding, EllipsisStyle, FourDotEllipsis, EntitiesSubstitution, GuillemetsSubstitution, InputFormat, NoBreakSpaceRule, NormalizationSubstitution, OrdinalsSubstitution, PrimesSubstitution, QElementsSubstitution, QuoteNestingSubstitution, QuotesSubstitution, SpacingSubstitution, SymbolsSubstitution, SubstitutionConfigHelpers};
 
 macro_rules! smartypants_tests {
     ($($name:ident: $value:expr,)*) => {
@@ -83,9 +85,905 @@ smartypants_tests! {
         SubstitutionConfig::default()
     ),
 
+    double_sets_of_quotes_at_the_start: (
+        r#""'Quoted' words in a larger quote.""#,
+        r#"&#8220;&#8216;Quoted&#8217; words in a larger quote.&#8221;"#,
+        SubstitutionConfig::default()
+    ),
+
+    double_sets_of_quotes_only_open_a_quotation: (
+        r#"<p><code>"x"</code>'s and "<em>Hello</em>"'s</p>"#,
+        r#"<p><code>"x"</code>&#8217;s and &#8220;<em>Hello</em>&#8221;&#8217;s</p>"#,
+        SubstitutionConfig::default()
+    ),
+
     decade_substitutions: (
         r#"It's the '80s"#,
         r#"It&#8217;s the &#8217;80s"#,
         SubstitutionConfig::default()
     ),
+
+    unicode_entities: (
+        r#""Isn't this fun?" -- I think so..."#,
+        "“Isn’t this fun?” – I think so…",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    named_entities: (
+        r#""Isn't this fun?" -- I think so..."#,
+        "&ldquo;Isn&rsquo;t this fun?&rdquo; &ndash; I think so&hellip;",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    ascii_equivalents: (
+        r#""Isn't this fun?" -- I think so..."#,
+        r#""Isn't this fun?" - I think so..."#,
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::AsciiEquivalents)
+    ),
+
+    escaped_characters: (
+        r#"\"Hi,\" he said. It\'s 3\.14"#,
+        "&#34;Hi,&#34; he said. It&#39;s 3&#46;14",
+        SubstitutionConfig::default()
+    ),
+
+    plain_text_keeps_angle_brackets: (
+        r#"x <y> "z""#,
+        "x <y> “z”",
+        SubstitutionConfig::default()
+            .with_input_format(InputFormat::PlainText)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    plain_text_is_escaped_for_html_output: (
+        r#"x <y> & "z""#,
+        "x &lt;y&gt; &amp; &#8220;z&#8221;",
+        SubstitutionConfig::default()
+            .with_input_format(InputFormat::PlainText)
+    ),
+
+    plain_text_paragraphs: (
+        "She said \"Wait\n\n\"No,\" he said. \\\"Really\\\"",
+        "She said “Wait\n\n“No,” he said. \"Really\"",
+        SubstitutionConfig::default()
+            .with_input_format(InputFormat::PlainText)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    plain_text_keeps_literal_entities: (
+        "it's &#8220; &amp; &#39;quoted&#39; & more...",
+        "it’s &#8220; &amp; &#39;quoted&#39; & more…",
+        SubstitutionConfig::default()
+            .with_input_format(InputFormat::PlainText)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    plain_text_keeps_literal_entities_in_ascii: (
+        "it's &#8220; -- &hellip;",
+        "it's &#8220; - &hellip;",
+        SubstitutionConfig::default()
+            .with_input_format(InputFormat::PlainText)
+            .with_entities(EntitiesSubstitution::AsciiEquivalents)
+    ),
+
+    attributes_are_left_alone_by_default: (
+        r#"<abbr title="Don't panic">DP</abbr>"#,
+        r#"<abbr title="Don't panic">DP</abbr>"#,
+        SubstitutionConfig::default()
+    ),
+
+    smartened_attributes: (
+        r#"<img src="don't-touch.png" alt="It's a &quot;photo&quot; -- probably">"#,
+        r#"<img src="don't-touch.png" alt="It&#8217;s a &#8220;photo&#8221; &#8211; probably">"#,
+        SubstitutionConfig::default()
+            .with_smartened_attributes(vec![String::from("alt")])
+    ),
+
+    smartened_attributes_are_escaped_for_their_delimiter: (
+        r#"<span title='Say "hi"' aria-label=Don't>"#,
+        r#"<span title='Say "hi"' aria-label="Don't">"#,
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::AsciiEquivalents)
+            .with_smartened_attributes(vec![String::from("title"), String::from("aria-label")])
+    ),
+
+    smartened_attributes_with_unicode_output: (
+        r#"<span title='It&#39;s "fine"'>"#,
+        r#"<span title='It’s “fine”'>"#,
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+            .with_smartened_attributes(vec![String::from("title")])
+    ),
+
+    smartened_meta_content: (
+        r#"<meta name="description" content="Isn't it nice?"><meta http-equiv="refresh" content="0; url='x'">"#,
+        r#"<meta name="description" content="Isn&#8217;t it nice?"><meta http-equiv="refresh" content="0; url='x'">"#,
+        SubstitutionConfig::default()
+            .with_smartened_attributes(vec![String::from("content")])
+    ),
+
+    escaped_quotes_are_left_alone_by_default: (
+        "&quot;Isn&#39;t this fun?&quot;",
+        "&quot;Isn&#39;t this fun?&quot;",
+        SubstitutionConfig::default()
+    ),
+
+    escaped_quotes: (
+        r#"&quot;Isn&#39;t this fun?&QUOT; <em>&#x22;</em>Yes&#34; \"dumb\" &apos;80s &#x27;hi&#039;"#,
+        r#"&#8220;Isn&#8217;t this fun?&#8221; <em>&#8220;</em>Yes&#8221; &#34;dumb&#34; &#8217;80s &#8216;hi&#8217;"#,
+        SubstitutionConfig::default()
+            .with_escaped_quotes(QuotesSubstitution::ConvertToCurly)
+    ),
+
+    closing_quotes_before_punctuation: (
+        r#""Hello", she said. "I can't, won't."#,
+        r#"&#8220;Hello&#8221;, she said. &#8220;I can&#8217;t, won&#8217;t."#,
+        SubstitutionConfig::default()
+    ),
+
+    entity_aware_context: (
+        "A&#160;\"quote\", a\u{a0}\"quote\", a&thinsp;'quote', a\u{2014}\"quote\" and \u{201c}'nested'\u{201d}",
+        "A&#160;&#8220;quote&#8221;, a\u{a0}&#8220;quote&#8221;, a&thinsp;&#8216;quote&#8217;, a\u{2014}&#8220;quote&#8221; and \u{201c}&#8216;nested&#8217;\u{201d}",
+        SubstitutionConfig::default()
+    ),
+
+    entity_aware_context_across_tokens: (
+        "<p>Hello&nbsp;<em>'</em>quote'</p>",
+        "<p>Hello&nbsp;<em>&#8216;</em>quote&#8217;</p>",
+        SubstitutionConfig::default()
+    ),
+
+    existing_typography_is_left_alone_by_default: (
+        "&ldquo;Don\u{2019}t&#8221; \u{2014} he said&hellip;",
+        "&ldquo;Don\u{2019}t&#8221; \u{2014} he said&hellip;",
+        SubstitutionConfig::default()
+    ),
+
+    normalized_typography: (
+        "&ldquo;Don\u{2019}t&#8221; \u{2014} he said&hellip; \"It\u{2032}s \u{201c}5\u{2032}\u{201d}, &#x2018;80s\u{2019} style&#x2013;ish...\"",
+        "“Don’t” — he said… “It’s “5\u{2032}”, ’80s’ style–ish…”",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+            .with_normalization(NormalizationSubstitution::Normalize)
+    ),
+
+    normalized_typography_with_named_entities: (
+        "“Hello,” she said \u{2013} &#x2018;hi&#8217;",
+        "&ldquo;Hello,&rdquo; she said &ndash; &lsquo;hi&rsquo;",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+            .with_normalization(NormalizationSubstitution::Normalize)
+    ),
+
+    normalized_typography_with_quotes_disabled: (
+        "&ldquo;Don&#8217;t&rdquo; &mdash; he said \"hi\"",
+        "“Don’t” — he said \"hi\"",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+            .with_normalization(NormalizationSubstitution::Normalize)
+            .with_quote_chars(QuotesSubstitution::DoNothing)
+    ),
+
+    german_quotes: (
+        r#"Er sagte: "Geht's dir 'gut'?""#,
+        "Er sagte: „Geht’s dir ‚gut‘?“",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("de"))
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    german_quotes_with_named_entities: (
+        r#""Hallo" -- &#8220;already curly&#8221;"#,
+        "&bdquo;Hallo&ldquo; &ndash; &ldquo;already curly&rdquo;",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("de-DE"))
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    french_quotes_with_named_entities: (
+        r#""C'est 'ça'""#,
+        "&laquo;&#8239;C&rsquo;est &ldquo;ça&rdquo;&#8239;&raquo;",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("fr"))
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    japanese_quotes_with_numeric_entities: (
+        r#""Hello 'world'""#,
+        "&#12300;Hello &#12302;world&#12303;&#12301;",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("ja"))
+    ),
+
+    swedish_quotes_with_ascii_equivalents: (
+        r#""Hej 'du'""#,
+        r#""Hej 'du'""#,
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("sv"))
+            .with_entities(EntitiesSubstitution::AsciiEquivalents)
+    ),
+
+    french_spacing: (
+        r#"Il a dit : "Vraiment?!" Oui; à 10:30 -- voir https://example.com/?q=1 :-)"#,
+        "Il a dit\u{202f}: «\u{202f}Vraiment\u{202f}?!\u{202f}» Oui\u{202f}; à 10:30 – voir https://example.com/?q=1 :-)",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("fr"))
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    french_spacing_replaces_existing_spaces: (
+        "<p>« Bonjour »&nbsp;! <em>Quoi</em> ?</p>",
+        "<p>«&#8239;Bonjour&#8239;»&#8239;! <em>Quoi</em>&#8239;?</p>",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("fr-FR"))
+    ),
+
+    french_spacing_after_inline_tags: (
+        r#"<p lang="fr">Il a lu "<em>Titre</em>". <strong>Attention</strong>!</p>"#,
+        "<p lang=\"fr\">Il a lu «\u{202f}<em>Titre</em>\u{202f}». <strong>Attention</strong>\u{202f}!</p>",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    french_spacing_is_not_added_at_the_start_of_a_block: (
+        "<p lang=\"fr\">Bonjour</p><p>!</p>",
+        "<p lang=\"fr\">Bonjour</p><p>!</p>",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("fr"))
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    french_spacing_can_be_turned_off: (
+        r#""Vraiment?""#,
+        "«Vraiment?»",
+        SubstitutionConfig::default()
+            .with_locale(Locale::new("fr"))
+            .with_punctuation_spacing(SpacingSubstitution::DoNothing)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    french_spacing_is_not_used_for_english: (
+        "Really? Yes: it is!",
+        "Really? Yes: it is!",
+        SubstitutionConfig::default()
+    ),
+
+    lang_attribute_switches_quote_style: (
+        r#"<p>He said "hi". <blockquote lang="de-DE"><p>Sie sagte "Hallo".</p></blockquote> "Bye."</p>"#,
+        r#"<p>He said “hi”. <blockquote lang="de-DE"><p>Sie sagte „Hallo“.</p></blockquote> “Bye.”</p>"#,
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    lang_attribute_on_root_element: (
+        r#"<html lang="fr"><body><p>"Quoi?" <span xml:lang='en'>"What?"</span><br lang="de"> "Oui!"</p></body></html>"#,
+        "<html lang=\"fr\"><body><p>«\u{202f}Quoi\u{202f}?\u{202f}» <span xml:lang='en'>“What?”</span><br lang=\"de\"> «\u{202f}Oui\u{202f}!\u{202f}»</p></body></html>",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    lang_attribute_applies_to_smartened_attributes: (
+        r#"<q lang="de" title="Er sagte &quot;ja&quot;">ja</q>"#,
+        r#"<q lang="de" title="Er sagte &#8222;ja&#8220;">ja</q>"#,
+        SubstitutionConfig::default()
+            .with_smartened_attributes(vec![String::from("title")])
+    ),
+
+    ascii_guillemets_are_left_alone_by_default: (
+        "<p>He said &lt;&lt;hello&gt;&gt;</p>",
+        "<p>He said &lt;&lt;hello&gt;&gt;</p>",
+        SubstitutionConfig::default()
+    ),
+
+    ascii_guillemets_are_converted: (
+        r#"<p><<citation>> and <a href="x">&lt;&lt;link&gt;&gt;</a> "quote"</p>"#,
+        r#"<p>«citation» and <a href="x">«link»</a> “quote”</p>"#,
+        SubstitutionConfig::default()
+            .with_ascii_guillemets(GuillemetsSubstitution::ConvertToGuillemets)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    ascii_guillemets_leave_tags_and_bit_shifts_alone: (
+        "<p>a<<b>bold</b>>, <code>1 << 3</code>, x << 2, 1 << 3 x >> y and <<<em>Titre</em>>></p>",
+        "<p>a<<b>bold</b>>, <code>1 << 3</code>, x << 2, 1 << 3 x >> y and «<em>Titre</em>»</p>",
+        SubstitutionConfig::default()
+            .with_ascii_guillemets(GuillemetsSubstitution::ConvertToGuillemets)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    ascii_guillemets_get_french_spacing: (
+        "<p lang=\"fr\"><<Bonjour>></p>",
+        "<p lang=\"fr\">&laquo;&#8239;Bonjour&#8239;&raquo;</p>",
+        SubstitutionConfig::default()
+            .with_ascii_guillemets(GuillemetsSubstitution::ConvertToGuillemets)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    ascii_guillemets_in_plain_text: (
+        "<<a>> < b >",
+        "&#171;a&#187; &lt; b &gt;",
+        SubstitutionConfig::default()
+            .with_ascii_guillemets(GuillemetsSubstitution::ConvertToGuillemets)
+            .with_input_format(InputFormat::PlainText)
+    ),
+
+    leading_elisions_get_apostrophes: (
+        "'Tis the season, 'twas said, rock 'n' roll, 'cause I love 'em. He said 'tis' twice.",
+        "’Tis the season, ’twas said, rock ’n’ roll, ’cause I love ’em. He said ’tis’ twice.",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    only_known_words_are_elisions: (
+        "He said 'hello' to 'Emma' and 'til.",
+        "He said ‘hello’ to ‘Emma’ and ’til.",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    elisions_use_the_locale: (
+        r#"<p lang="fr">Voici l'<em>ami</em> de <i>L'</i>« Homme »</p> <p lang="it">dell'<b>arte</b></p> <p lang="nl">Ik woon in 's-Hertogenbosch, 't is mooi</p>"#,
+        "<p lang=\"fr\">Voici l’<em>ami</em> de <i>L’</i>«\u{202f}Homme\u{202f}»</p> <p lang=\"it\">dell’<b>arte</b></p> <p lang=\"nl\">Ik woon in ’s-Hertogenbosch, ’t is mooi</p>",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    elisions_can_be_added_by_the_user: (
+        "See you later, 'gator. 'Gator's tail.",
+        "See you later, ’gator. ’Gator’s tail.",
+        SubstitutionConfig::default()
+            .with_elisions(vec![String::from("'gator")])
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    apostrophes_can_use_a_modifier_letter: (
+        "'Hello' in Ukrainian is прив'ет, and 'tis m'ята, isn't it?",
+        "‘Hello’ in Ukrainian is привʼет, and ʼtis mʼята, isnʼt it?",
+        SubstitutionConfig::default()
+            .with_apostrophe(ApostropheSubstitution::ModifierLetterApostrophe)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    apostrophes_can_use_a_turned_comma: (
+        "Hawai'i's 'state fish'",
+        "Hawai&#699;i&#699;s &lsquo;state fish&rsquo;",
+        SubstitutionConfig::default()
+            .with_apostrophe(ApostropheSubstitution::ModifierLetterTurnedComma)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    quote_nesting_alternates_quotes_by_depth: (
+        r#"<p>She said 'he told me "no" twice' and left.</p><p>"Goin' home," he said.</p>"#,
+        r#"<p>She said “he told me ‘no’ twice” and left.</p><p>“Goin’ home,” he said.</p>"#,
+        SubstitutionConfig::default()
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    quote_nesting_uses_the_locale: (
+        r#"<p lang="de">"Er sagte "nein" dazu"</p>"#,
+        r#"<p lang="de">„Er sagte ‚nein‘ dazu“</p>"#,
+        SubstitutionConfig::default()
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    quote_nesting_is_tracked_across_tags: (
+        r#"<p>"He said 'it's <em>fine</em>' and went"</p>"#,
+        r#"<p>&#8220;He said &#8216;it&#8217;s <em>fine</em>&#8217; and went&#8221;</p>"#,
+        SubstitutionConfig::default()
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting)
+    ),
+
+    block_tags_reset_quote_context: (
+        r#"<p>Hello.</p><p>"<em>Hi</em>"</p><p>One<br>'<em>two</em>'</p>"#,
+        r#"<p>Hello.</p><p>&#8220;<em>Hi</em>&#8221;</p><p>One<br>&#8216;<em>two</em>&#8217;</p>"#,
+        SubstitutionConfig::default()
+    ),
+
+    quote_nesting_carries_quotes_across_paragraphs: (
+        "<p>\"It was a dark night, and 'cold.</p>\n<p>\"'And stormy,' she said.\"</p>",
+        "<p>“It was a dark night, and ‘cold.</p>\n<p>“‘And stormy,’ she said.”</p>",
+        SubstitutionConfig::default()
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    quotes_see_context_across_inline_tags: (
+        r##""<em>Hello</em>" and <a href="#">don</a>'t, '<strong>Hi</strong>', she said "<em>no</em>," then"##,
+        "“<em>Hello</em>” and <a href=\"#\">don</a>’t, ‘<strong>Hi</strong>’, she said “<em>no</em>,” then",
+        SubstitutionConfig::default()
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    quotes_see_context_across_nested_inline_tags: (
+        r##"<p>"'<em><a href="#">x</a></em>' is it," rock <b>'n'</b> roll in the <b>'80s</b></p>"##,
+        r##"<p>&#8220;&#8216;<em><a href="#">x</a></em>&#8217; is it,&#8221; rock <b>&#8217;n&#8217;</b> roll in the <b>&#8217;80s</b></p>"##,
+        SubstitutionConfig::default()
+    ),
+
+    q_elements_are_left_alone_by_default: (
+        r#"<p><q>"Hello"</q></p>"#,
+        r#"<p><q>&#8220;Hello&#8221;</q></p>"#,
+        SubstitutionConfig::default()
+    ),
+
+    q_elements_can_have_inner_quotes_stripped: (
+        r#"<p>He said <q cite="x">"Hello, it's me"</q> and <q>'Bye'</q> and <q>goin' home'</q>.</p>"#,
+        r#"<p>He said <q cite="x">Hello, it&#8217;s me</q> and <q>Bye</q> and <q>goin&#8217; home&#8217;</q>.</p>"#,
+        SubstitutionConfig::default()
+            .with_q_elements(QElementsSubstitution::StripInnerQuotes)
+    ),
+
+    q_elements_can_be_replaced_with_quotes: (
+        r#"<p>He said <q>"I heard <q>hi</q>, don't"</q>.</p>"#,
+        "<p>He said “I heard ‘hi’, don’t”.</p>",
+        SubstitutionConfig::default()
+            .with_q_elements(QElementsSubstitution::ReplaceWithQuotes)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    q_elements_use_the_locale_and_french_spacing: (
+        r#"<p lang="fr">Il a dit <q>bonjour</q> et <q lang="de">hallo</q>.</p>"#,
+        "<p lang=\"fr\">Il a dit «\u{202f}bonjour\u{202f}» et „hallo“.</p>",
+        SubstitutionConfig::default()
+            .with_q_elements(QElementsSubstitution::ReplaceWithQuotes)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    q_elements_follow_quote_nesting: (
+        r#"<p>"She said <q>hi</q> to me"</p>"#,
+        "<p>“She said ‘hi’ to me”</p>",
+        SubstitutionConfig::default()
+            .with_q_elements(QElementsSubstitution::ReplaceWithQuotes)
+            .with_quote_nesting(QuoteNestingSubstitution::TrackNesting)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    primes_are_off_by_default: (
+        r#"He is 5'10" tall"#,
+        "He is 5&#8217;10&#8221; tall",
+        SubstitutionConfig::default()
+    ),
+
+    primes_for_feet_and_inches: (
+        r#"He is 5'10" tall, she is 6' and 2' 30" is the time, with a 12" pizza"#,
+        "He is 5′10″ tall, she is 6′ and 2′ 30″ is the time, with a 12″ pizza",
+        SubstitutionConfig::default()
+            .with_primes(PrimesSubstitution::ConvertToPrimes)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    primes_for_coordinates: (
+        r#"It's at 40°26'46"N 79°58'56.5"W or 40&deg; 26' 46" N."#,
+        "It&#8217;s at 40°26&#8242;46&#8243;N 79°58&#8242;56.5&#8243;W or 40&deg; 26&#8242; 46&#8243; N.",
+        SubstitutionConfig::default()
+            .with_primes(PrimesSubstitution::ConvertToPrimes)
+    ),
+
+    primes_dont_replace_closing_quotes: (
+        r#""Take 5" and 'Catch 22' in the '80s, then <b>5</b>'10""#,
+        "&ldquo;Take 5&rdquo; and &lsquo;Catch 22&rsquo; in the &rsquo;80s, then <b>5</b>&prime;10&Prime;",
+        SubstitutionConfig::default()
+            .with_primes(PrimesSubstitution::ConvertToPrimes)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    primes_in_ascii: (
+        r#"5'10""#,
+        r#"5'10""#,
+        SubstitutionConfig::default()
+            .with_primes(PrimesSubstitution::ConvertToPrimes)
+            .with_entities(EntitiesSubstitution::AsciiEquivalents)
+    ),
+
+    spaced_hyphens_are_left_alone_by_default: (
+        "Tea - or coffee - is served",
+        "Tea - or coffee - is served",
+        SubstitutionConfig::default()
+    ),
+
+    spaced_hyphens_to_en_dashes: (
+        "<p>Tea - or coffee - is served</p>\n<p>She said &#8220;no&#8221; - then left</p>",
+        "<p>Tea &#8211; or coffee &#8211; is served</p>\n<p>She said &#8220;no&#8221; &#8211; then left</p>",
+        SubstitutionConfig::default()
+            .with_single_spaced_hyphen(DashesSubstitution::EnDash)
+    ),
+
+    spaced_hyphens_to_em_dashes_across_tags: (
+        "Tea <b>hot</b> - or coffee",
+        "Tea <b>hot</b> &#8212; or coffee",
+        SubstitutionConfig::default()
+            .with_single_spaced_hyphen(DashesSubstitution::EmDash)
+    ),
+
+    spaced_hyphens_skip_list_markers_and_pre: (
+        "Shopping:\n - eggs\n - milk\n<ul><li> - bread</li></ul>\n<pre>a - b</pre> - done",
+        "Shopping:\n - eggs\n - milk\n<ul><li> - bread</li></ul>\n<pre>a - b</pre> &#8211; done",
+        SubstitutionConfig::default()
+            .with_single_spaced_hyphen(DashesSubstitution::EnDash)
+    ),
+
+    dash_spacing_is_left_alone_by_default: (
+        "Tea---or coffee --- is served",
+        "Tea&#8212;or coffee &#8212; is served",
+        SubstitutionConfig::default()
+    ),
+
+    dash_spacing_closed_em_dashes: (
+        "Tea --- or coffee&nbsp;&mdash; is served, 1912--2004",
+        "Tea&#8212;or coffee&mdash;is served, 1912&#8211;2004",
+        SubstitutionConfig::default()
+            .with_em_dash_spacing(DashSpacing::Closed)
+            .with_en_dash_spacing(DashSpacing::Closed)
+    ),
+
+    dash_spacing_spaced_dashes: (
+        "Tea---or coffee—is served, tea -- or coffee, 1912--2004",
+        "Tea — or coffee — is served, tea – or coffee, 1912–2004",
+        SubstitutionConfig::default()
+            .with_em_dash_spacing(DashSpacing::Spaced)
+            .with_en_dash_spacing(DashSpacing::Spaced)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    dash_spacing_hair_and_thin_spaces: (
+        "Tea --- or coffee -- is served",
+        "Tea&#8202;&#8212;&#8202;or coffee&#8239;&#8211;&#8239;is served",
+        SubstitutionConfig::default()
+            .with_em_dash_spacing(DashSpacing::HairSpaces)
+            .with_en_dash_spacing(DashSpacing::ThinNoBreakSpaces)
+    ),
+
+    dash_spacing_keeps_indentation_and_looks_across_tags: (
+        "<p>--- Heraclitus</p>\n<p><b>Tea</b> --- <i>coffee</i></p>\nTea\n  --- coffee",
+        "<p>&#8212;Heraclitus</p>\n<p><b>Tea</b>&#8212;<i>coffee</i></p>\nTea\n  &#8212;coffee",
+        SubstitutionConfig::default()
+            .with_em_dash_spacing(DashSpacing::Closed)
+    ),
+
+    ellipses_preset_keeps_the_fourth_dot: (
+        "Wait.... What? So . . . . And......",
+        "Wait&#8230;. What? So &#8230; . And&#8230;&#8230;",
+        SubstitutionConfig::default()
+    ),
+
+    ellipses_as_spaced_periods: (
+        "Wait... what? Then.... Next",
+        "Wait.&nbsp;.&nbsp;. what? Then. .&nbsp;.&nbsp;. Next",
+        SubstitutionConfig::default()
+            .with_ellipses(EllipsesSubstitution::Custom(EllipsesOptions {
+                style: EllipsisStyle::SpacedPeriods,
+                four_dots: FourDotEllipsis::PeriodThenEllipsis,
+                space_before: EllipsisSpaceBinding::DoNothing,
+            }))
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    ellipses_with_four_dots: (
+        "End.... Next. . . .",
+        "End.&#8230; Next.&#8230;",
+        SubstitutionConfig::default()
+            .with_ellipses(EllipsesSubstitution::Custom(EllipsesOptions {
+                four_dots: FourDotEllipsis::PeriodThenEllipsis,
+                ..EllipsesOptions::default()
+            }))
+    ),
+
+    ellipses_can_drop_the_fourth_dot: (
+        "End.... Next",
+        "End&#8230; Next",
+        SubstitutionConfig::default()
+            .with_ellipses(EllipsesSubstitution::Custom(EllipsesOptions {
+                four_dots: FourDotEllipsis::Ellipsis,
+                ..EllipsesOptions::default()
+            }))
+    ),
+
+    ellipses_bound_with_a_no_break_space: (
+        "Well ... maybe. Or...not",
+        "Well\u{a0}… maybe. Or…not",
+        SubstitutionConfig::default()
+            .with_ellipses(EllipsesSubstitution::Custom(EllipsesOptions {
+                space_before: EllipsisSpaceBinding::NoBreakSpace,
+                ..EllipsesOptions::default()
+            }))
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    symbols_are_left_alone_by_default: (
+        "(c) 2024 Acme(r) c/o Widgets(tm) (p)",
+        "(c) 2024 Acme(r) c/o Widgets(tm) (p)",
+        SubstitutionConfig::default()
+    ),
+
+    symbols_in_named_entities: (
+        "(c) 2024 Acme(r) c/o Widgets(tm) (P) +/- 1",
+        "&copy; 2024 Acme&reg; &incare; Widgets&trade; &copysr; &plusmn; 1",
+        SubstitutionConfig::default()
+            .with_copyright_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_registered_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_trademark_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_sound_recording_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_care_of_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_plus_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    symbols_in_unicode_characters: (
+        "\"Acme(TM)\" (c) 2024",
+        "“Acme™” © 2024",
+        SubstitutionConfig::default()
+            .with_copyright_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_trademark_symbol(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    arrows_are_left_alone_by_default: (
+        "a -> b --> c",
+        "a -> b &#8211;> c",
+        SubstitutionConfig::default()
+    ),
+
+    arrows_and_operators_in_html: (
+        "<p>Click <b>File</b> -> Save, then x <= 5 <- y --> z and a != b</p><!-- keep -> this -->",
+        "<p>Click <b>File</b> &rarr; Save, then x &le; 5 &larr; y &rarr; z and a &ne; b</p><!-- keep -> this -->",
+        SubstitutionConfig::default()
+            .with_arrows(SymbolsSubstitution::ConvertToSymbol)
+            .with_comparison_operators(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    arrows_with_escaped_angle_brackets: (
+        "x &lt;= 5 &lt;-&gt; y =&gt; z",
+        "x ≤ 5 ↔ y ⇒ z",
+        SubstitutionConfig::default()
+            .with_arrows(SymbolsSubstitution::ConvertToSymbol)
+            .with_comparison_operators(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    no_break_spaces_are_off_by_default: (
+        "Mr. Smith weighs 80 kg",
+        "Mr. Smith weighs 80 kg",
+        SubstitutionConfig::default()
+    ),
+
+    no_break_spaces_in_named_entities: (
+        "<p>Mr. <b>Smith</b> weighs 80 kg, see p. 42 -- or not ...</p>",
+        "<p>Mr.&nbsp;<b>Smith</b> weighs 80&nbsp;kg, see p.&nbsp;42 &ndash; or not&nbsp;&hellip;</p>",
+        SubstitutionConfig::default()
+            .with_no_break_spaces(vec![
+                NoBreakSpaceRule::NumberAndUnit,
+                NoBreakSpaceRule::AbbreviationAndNumber,
+                NoBreakSpaceRule::HonorificAndName,
+                NoBreakSpaceRule::BeforeEllipsis,
+            ])
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    no_break_spaces_in_numeric_entities: (
+        "Tea --- and \"coffee\"",
+        "Tea &#8212;&#160;and &#8220;coffee&#8221;",
+        SubstitutionConfig::default()
+            .with_no_break_spaces(vec![NoBreakSpaceRule::AfterEmDash])
+    ),
+
+    no_break_spaces_in_unicode: (
+        r#"<p lang="cs">Byl v Praze a v Brně.</p><p>I was in a v Prague.</p>"#,
+        "<p lang=\"cs\">Byl v\u{a0}Praze a\u{a0}v\u{a0}Brně.</p><p>I was in a v Prague.</p>",
+        SubstitutionConfig::default()
+            .with_no_break_spaces(vec![NoBreakSpaceRule::ShortWords])
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    ordinals_are_left_alone_by_default: (
+        "The 1st and 2nd",
+        "The 1st and 2nd",
+        SubstitutionConfig::default()
+    ),
+
+    ordinals_in_html: (
+        r#"<p>The 1st, 22nd and <code>3rd</code> <span lang="fr">1er et 2e</span> <span lang="es">1.o</span></p>"#,
+        r#"<p>The 1<sup>st</sup>, 22<sup>nd</sup> and <code>3rd</code> <span lang="fr">1<sup>er</sup> et 2<sup>e</sup></span> <span lang="es">1.&ordm;</span></p>"#,
+        SubstitutionConfig::default()
+            .with_ordinals(OrdinalsSubstitution::Superscript)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    ordinals_in_plain_text: (
+        "The 23rd <b>floor</b>",
+        "The 23ʳᵈ <b>floor</b>",
+        SubstitutionConfig::default()
+            .with_ordinals(OrdinalsSubstitution::Superscript)
+            .with_input_format(InputFormat::PlainText)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    ordinals_in_attributes: (
+        r#"<img alt="The 4th">"#,
+        r#"<img alt="The 4&#7511;&#688;">"#,
+        SubstitutionConfig::default()
+            .with_ordinals(OrdinalsSubstitution::Superscript)
+            .with_smartened_attributes(vec![String::from("alt")])
+    ),
+
+    fractions_are_left_alone_by_default: (
+        "Add 1/2 cup of flour",
+        "Add 1/2 cup of flour",
+        SubstitutionConfig::default()
+    ),
+
+    fractions_in_html: (
+        "<p>Add 2 1/3 cups, a 3/16\" bolt and <a href=\"/img/1/2\">1/2/2022</a></p>",
+        "<p>Add 2&frac13; cups, a <sup>3</sup>&frasl;<sub>16</sub>&rdquo; bolt and <a href=\"/img/1/2\">1/2/2022</a></p>",
+        SubstitutionConfig::default()
+            .with_fractions(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+
+    fractions_in_unicode: (
+        "Add 3/4 cup and 5/16 tsp, 24/7",
+        "Add ¾ cup and ⁵⁄₁₆ tsp, 24/7",
+        SubstitutionConfig::default()
+            .with_fractions(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    fractions_in_attributes_dont_use_markup: (
+        r#"<img alt="A 5/16 bolt">"#,
+        r#"<img alt="A &#8309;&#8260;&#8321;&#8326; bolt">"#,
+        SubstitutionConfig::default()
+            .with_fractions(SymbolsSubstitution::ConvertToSymbol)
+            .with_smartened_attributes(vec![String::from("alt")])
+    ),
+
+    fractions_include_days_and_months_without_a_year: (
+        "On 9/11 and 1/2/2022",
+        "On <sup>9</sup>&#8260;<sub>11</sub> and 1/2/2022",
+        SubstitutionConfig::default()
+            .with_fractions(SymbolsSubstitution::ConvertToSymbol)
+    ),
+
+    numbers_are_left_alone_by_default: (
+        "It's -5 outside, the screen is 1920 x 1080, see pp. 10-20",
+        "It&#8217;s -5 outside, the screen is 1920 x 1080, see pp. 10-20",
+        SubstitutionConfig::default()
+    ),
+
+    numbers_get_mathematical_symbols: (
+        "It's -5 +-2 outside, a 3x4 grid on a 1920 x 1080 screen, see pp. 10-20 (1912-2004)",
+        "It’s −5 ±2 outside, a 3×4 grid on a 1920 × 1080 screen, see pp. 10–20 (1912–2004)",
+        SubstitutionConfig::default()
+            .with_number_ranges(DashesSubstitution::EnDash)
+            .with_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_multiplication_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_plus_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::UnicodeCharacters)
+    ),
+
+    numbers_skip_dates_phone_numbers_and_identifiers: (
+        "On 2022-02-08 call 555-1234 about COVID-19, ISBN 978-3-16, id 0x1F -- or 1990--95",
+        "On 2022-02-08 call 555-1234 about COVID-19, ISBN 978-3-16, id 0x1F &#8211; or 1990&#8211;95",
+        SubstitutionConfig::default()
+            .with_number_ranges(DashesSubstitution::EnDash)
+            .with_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_multiplication_sign(SymbolsSubstitution::ConvertToSymbol)
+    ),
+
+    numbers_in_named_entities: (
+        "-5 +- 1",
+        "&minus;5 &plusmn; 1",
+        SubstitutionConfig::default()
+            .with_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_plus_minus_sign(SymbolsSubstitution::ConvertToSymbol)
+            .with_entities(EntitiesSubstitution::HtmlNamedEntities)
+    ),
+}
+
+#[test]
+fn warnings_are_empty_for_balanced_quotes() {
+    let (_, warnings) = smartypants_with_warnings(
+        r#"<p>"Hello," she said, "it's 'fine'."</p>"#,
+        &SubstitutionConfig::default()
+    );
+
+    assert_eq!(warnings, vec![]);
+}
+
+#[test]
+fn warnings_report_unbalanced_quotes_with_byte_offsets() {
+    let input = "<p>\u{2014} \"Hello, she said.</p>\n<p>Fine,\" he said.</p>";
+    let (output, warnings) = smartypants_with_warnings(input, &SubstitutionConfig::default());
+
+    assert_eq!(output, smartypants(input, &SubstitutionConfig::default()));
+    assert_eq!(warnings, vec![
+        Warning { kind: WarningKind::UnclosedQuote, offset: 7 },
+        Warning { kind: WarningKind::UnopenedQuote, offset: 37 },
+    ]);
+    assert_eq!(&input[7..8], "\"");
+    assert_eq!(&input[37..38], "\"");
+}
+
+#[test]
+fn warnings_allow_quotes_carried_across_paragraphs() {
+    let input = "\"It was a dark night.\n\n\"And stormy,\" she said.";
+    let config = SubstitutionConfig::default().with_input_format(InputFormat::PlainText);
+
+    let (_, warnings) = smartypants_with_warnings(input, &config);
+    assert_eq!(warnings, vec![]);
+
+    let (_, warnings) = smartypants_with_warnings("<p>\"One.</p><p>Two.</p><p>\"Three.</p>", &SubstitutionConfig::default());
+    assert_eq!(warnings, vec![
+        Warning { kind: WarningKind::UnclosedQuote, offset: 3 },
+        Warning { kind: WarningKind::UnclosedQuote, offset: 26 },
+    ]);
 }
+
+#[test]
+fn warnings_ignore_primes() {
+    let input = "<p>He is 5'10\" tall, \"really\"</p>";
+    let config = SubstitutionConfig::default().with_primes(PrimesSubstitution::ConvertToPrimes);
+
+    let (_, warnings) = smartypants_with_warnings(input, &config);
+    assert_eq!(warnings, vec![]);
+
+    let (_, warnings) = smartypants_with_warnings(input, &SubstitutionConfig::default());
+    assert_eq!(warnings, vec![Warning { kind: WarningKind::UnopenedQuote, offset: 13 }]);
+}
+
+#[test]
+fn warnings_ignore_primes_after_an_inline_tag() {
+    let input = "<p><b>5</b>'10\" and \"really</p>";
+    let config = SubstitutionConfig::default().with_primes(PrimesSubstitution::ConvertToPrimes);
+
+    let (_, warnings) = smartypants_with_warnings(input, &config);
+    assert_eq!(warnings, vec![Warning { kind: WarningKind::UnclosedQuote, offset: 20 }]);
+    assert_eq!(&input[20..21], "\"");
+}
+
+#[test]
+fn warnings_count_quotes_converted_by_single_backticks() {
+    let input = "it's `b and `a'";
+    let config = SubstitutionConfig::default()
+        .with_quote_chars(QuotesSubstitution::DoNothing)
+        .with_single_backticks(QuotesSubstitution::ConvertToCurly);
+
+    let (_, warnings) = smartypants_with_warnings(input, &config);
+    assert_eq!(warnings, vec![Warning { kind: WarningKind::UnclosedQuote, offset: 5 }]);
+}
+
+#[test]
+fn arrows_and_operators_in_a_long_text() {
+    let prose = "The quick brown fox jumps over the lazy dog. ".repeat(2500);
+    let input = format!("<p>Go -> {} and x >= 3 <- here</p>", prose);
+    let config = SubstitutionConfig::default()
+        .with_arrows(SymbolsSubstitution::ConvertToSymbol)
+        .with_comparison_operators(SymbolsSubstitution::ConvertToSymbol)
+        .with_entities(EntitiesSubstitution::UnicodeCharacters);
+
+    assert_eq!(smartypants(&input, &config), format!("<p>Go → {} and x ≥ 3 ← here</p>", prose));
+}
+
+#[test]
+fn no_break_spaces_in_a_long_text() {
+    let numbers = "1 2 ".repeat(30000);
+    let input = format!("<p>Mr. Smith ran 10 km. {} See p. 42 ...</p>", numbers);
+    let config = SubstitutionConfig::default()
+        .with_no_break_spaces(vec![
+            NoBreakSpaceRule::NumberAndUnit,
+            NoBreakSpaceRule::AbbreviationAndNumber,
+            NoBreakSpaceRule::HonorificAndName,
+            NoBreakSpaceRule::BeforeEllipsis,
+        ]);
+
+    assert_eq!(
+        smartypants(&input, &config),
+        format!("<p>Mr.&#160;Smith ran 10&#160;km. {} See p.&#160;42&#160;&#8230;</p>", numbers)
+    );
+}
+
+
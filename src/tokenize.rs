@@ -18,9 +18,14 @@
 //      to see whether we're inside a tag where we don't want to do any
 //      text conversions.
 //
+// Plain text input doesn't have any tags, so it gets a simpler tokenizer:
+// it only splits the text into paragraphs, so that each paragraph starts
+// with a clean slate when we decide which way a quote should curl.
+//
 // The tests at the bottom of this file give some examples of how HTML strings
 // get tokenized.
 
+use fancy_regex::Regex as FancyRegex;
 use regex::Regex;
 
 // Other SmartyPants implementations (e.g. Perl, Python, Ruby) store
@@ -32,6 +37,7 @@ use regex::Regex;
 pub enum Token {
     Tag(String),
     Text(String),
+    ParagraphBreak(String),
 }
 
 impl PartialEq for Token {
@@ -39,6 +45,7 @@ impl PartialEq for Token {
         match (self, other) {
             (Token::Tag(tag_self), Token::Tag(tag_other))     => tag_self == tag_other,
             (Token::Text(text_self), Token::Text(text_other)) => text_self == text_other,
+            (Token::ParagraphBreak(brk_self), Token::ParagraphBreak(brk_other)) => brk_self == brk_other,
             _ => false,
         }
     }
@@ -53,18 +60,52 @@ impl PartialEq for Token {
 //    - the 'tag' group is optional -- that allows us to get any text
 //      that comes after the final tag as the final Capture, rather
 //      than slicing into the string
+//    - some conversions need a few things that start with an opening
+//      angle bracket (<) to be treated as text, e.g. `<<` when we're
+//      converting ASCII guillemets, or `<-` and `<=` when we're
+//      converting arrows and operators
 //
-lazy_static! {
-    static ref TAG_SOUP: Regex = Regex::new(r#"(?x)
-        (?P<text>[^<]*)         # anything not an opening angle bracket (<)
+fn tag_soup(text_exceptions: &[&str]) -> FancyRegex {
+    let text = std::iter::once("[^<]")
+        .chain(text_exceptions.iter().copied())
+        .collect::<Vec<_>>()
+        .join("|");
+
+    FancyRegex::new(&format!(r#"(?x)
+        (?P<text>(?:{})*)       # anything not an opening angle bracket (<),
+                                # or one of the exceptions
         (?P<tag>
-            <!--.*?--\\s*>      # anything like an HTML comment <!-- … -->
+            <!--.*?--\s*>       # anything like an HTML comment <!-- … -->
             |                   #   or
             <[^>]*>             # an opening angle bracket (<), then any
                                 # number of chars that aren't a closing
                                 # angle bracket (>)
         )?
-    "#).unwrap();
+    "#, text)).unwrap()
+}
+
+// A pair of opening angle brackets (<<) is text, unless it's the start of
+// a tag, e.g. the `<<b>` in `a<<b>bold</b>>`.  We can tell them apart if
+// the guillemets pair up and are attached to the text inside them
+// (`<<citation>>`), or if the brackets aren't followed by something that
+// could start a tag (`<< Bonjour`, `1 << 3`).  Otherwise, only the first
+// bracket is text.
+const ASCII_GUILLEMETS: &[&str] = &[
+    r"<<(?!\s)[^<>]*(?<!\s)>>",
+    "<<(?![A-Za-z/!?<])",
+    "<(?=<)",
+];
+
+const ARROWS: &[&str] = &["<-", "<="];
+
+lazy_static! {
+    static ref TAG_SOUP: FancyRegex = tag_soup(&[]);
+
+    static ref TAG_SOUP_WITH_GUILLEMETS: FancyRegex = tag_soup(ASCII_GUILLEMETS);
+
+    static ref TAG_SOUP_WITH_ARROWS: FancyRegex = tag_soup(ARROWS);
+
+    static ref TAG_SOUP_WITH_GUILLEMETS_AND_ARROWS: FancyRegex = tag_soup(&[ASCII_GUILLEMETS, ARROWS].concat());
 }
 
 /// Returns an array of tokens comprising the input string.
@@ -85,9 +126,32 @@ lazy_static! {
 ///     https://web.archive.org/web/20041215155739/http://bradchoate.com/weblog/2002/07/27/mtregex
 ///
 pub fn tokenize(text: &str) -> Vec<Token> {
+    tokenize_with_regex(text, &TAG_SOUP)
+}
+
+/// Returns an array of tokens comprising the input string, treating some
+/// things that start with `<` as text rather than the start of a tag.
+///
+/// If `ascii_guillemets` is true, `<<` is text, so that e.g. `<<citation>>`
+/// isn't mistaken for a `<citation>` tag.  If `arrows` is true, `<-` and
+/// `<=` are text, so that e.g. `a <- b` isn't mistaken for a tag either.
+pub fn tokenize_with_options(text: &str, ascii_guillemets: bool, arrows: bool) -> Vec<Token> {
+    let tag_soup = match (ascii_guillemets, arrows) {
+        (false, false) => return tokenize(text),
+        (true,  false) => &*TAG_SOUP_WITH_GUILLEMETS,
+        (false, true)  => &*TAG_SOUP_WITH_ARROWS,
+        (true,  true)  => &*TAG_SOUP_WITH_GUILLEMETS_AND_ARROWS,
+    };
+
+    tokenize_with_regex(text, tag_soup)
+}
+
+fn tokenize_with_regex(text: &str, tag_soup: &FancyRegex) -> Vec<Token> {
     let mut tokens = vec!();
 
-    for cap in (*TAG_SOUP).captures_iter(text) {
+    for cap in tag_soup.captures_iter(text) {
+        let cap = cap.unwrap();
+
         if !cap["text"].is_empty() {
             tokens.push(Token::Text(cap["text"].to_owned()));
         }
@@ -107,19 +171,45 @@ pub fn tokenize(text: &str) -> Vec<Token> {
         // [1]: http://www.w3.org/TR/REC-html40/intro/sgmltut.html#h-3.2.4
         // [2]: http://www.w3.org/TR/html5/syntax.html#comments
         //
-        match cap.name("tag") {
-            Some(tag_match) => {
-                let tag = tag_match.as_str();
+        if let Some(tag_match) = cap.name("tag") {
+            let tag = tag_match.as_str();
 
-                if is_comment(tag) && comment_text(tag).contains("--") {
-                    tokens.push(Token::Text(cap["tag"].to_owned()));
-                    continue;
-                }
+            if is_comment(tag) && comment_text(tag).contains("--") {
+                tokens.push(Token::Text(cap["tag"].to_owned()));
+                continue;
+            }
 
-                tokens.push(Token::Tag(cap["tag"].to_owned()));
-            },
-            _ => (),
-        };
+            tokens.push(Token::Tag(cap["tag"].to_owned()));
+        }
+    }
+
+    tokens
+}
+
+lazy_static! {
+    static ref BLANK_LINES: Regex = Regex::new(r"(?:\r?\n[ \t]*){2,}").unwrap();
+}
+
+/// Returns an array of tokens comprising a plain text string.
+///
+/// Plain text has no tags, so `<` and `>` are left in the text tokens.
+/// Runs of blank lines are returned as paragraph breaks, and everything
+/// else is returned as text.
+pub fn tokenize_plain_text(text: &str) -> Vec<Token> {
+    let mut tokens = vec!();
+    let mut last_end = 0;
+
+    for brk in (*BLANK_LINES).find_iter(text) {
+        if brk.start() > last_end {
+            tokens.push(Token::Text(text[last_end..brk.start()].to_owned()));
+        }
+
+        tokens.push(Token::ParagraphBreak(brk.as_str().to_owned()));
+        last_end = brk.end();
+    }
+
+    if last_end < text.len() {
+        tokens.push(Token::Text(text[last_end..].to_owned()));
     }
 
     tokens
@@ -142,7 +232,7 @@ fn comment_text(comment: &str) -> &str {
 
 #[cfg(test)]
 mod tests {
-    use crate::tokenize::{tokenize, Token};
+    use crate::tokenize::{tokenize, tokenize_plain_text, tokenize_with_options, Token};
 
     #[test]
     fn it_handles_a_simple_string() {
@@ -180,6 +270,15 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn it_handles_a_comment_containing_a_closing_angle_bracket() {
+        let result = tokenize("<!-- a > b -->");
+        let expected = vec![
+            Token::Tag(String::from("<!-- a > b -->")),
+        ];
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn it_handles_a_complex_example() {
         let result = tokenize("Some text <em>with emphasis</em> and <span class=\"big\">inline attributes</span>.");
@@ -196,4 +295,101 @@ mod tests {
         ];
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn it_leaves_angle_brackets_in_plain_text() {
+        let result = tokenize_plain_text("x <y> z");
+        let expected = vec![Token::Text(String::from("x <y> z"))];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_splits_plain_text_on_blank_lines() {
+        let result = tokenize_plain_text("First line\nstill first\n\n  \nSecond");
+        let expected = vec![
+            Token::Text(String::from("First line\nstill first")),
+            Token::ParagraphBreak(String::from("\n\n  \n")),
+            Token::Text(String::from("Second")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_treats_ascii_guillemets_as_text() {
+        let result = tokenize_with_options("<p><<citation>> and <b>bold</b></p>", true, false);
+        let expected = vec![
+            Token::Tag(String::from("<p>")),
+            Token::Text(String::from("<<citation>> and ")),
+            Token::Tag(String::from("<b>")),
+            Token::Text(String::from("bold")),
+            Token::Tag(String::from("</b>")),
+            Token::Tag(String::from("</p>")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_doesnt_treat_the_start_of_a_tag_as_ascii_guillemets() {
+        let result = tokenize_with_options("a<<b>bold</b>>", true, false);
+        let expected = vec![
+            Token::Text(String::from("a<")),
+            Token::Tag(String::from("<b>")),
+            Token::Text(String::from("bold")),
+            Token::Tag(String::from("</b>")),
+            Token::Text(String::from(">")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_treats_a_bit_shift_as_text() {
+        let result = tokenize_with_options("<code>1 << 3</code> and <<<em>Titre</em>>>", true, false);
+        let expected = vec![
+            Token::Tag(String::from("<code>")),
+            Token::Text(String::from("1 << 3")),
+            Token::Tag(String::from("</code>")),
+            Token::Text(String::from(" and <<")),
+            Token::Tag(String::from("<em>")),
+            Token::Text(String::from("Titre")),
+            Token::Tag(String::from("</em>")),
+            Token::Text(String::from(">>")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_only_pairs_up_guillemets_attached_to_the_text_inside_them() {
+        let result = tokenize_with_options("<<citation>> and 1 << 3 x >> y", true, false);
+        assert_eq!(result, vec![Token::Text(String::from("<<citation>> and 1 << 3 x >> y"))]);
+
+        let result = tokenize_with_options("<<b >>", true, false);
+        let expected = vec![
+            Token::Text(String::from("<")),
+            Token::Tag(String::from("<b >")),
+            Token::Text(String::from(">")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_treats_ascii_guillemets_as_tags_by_default() {
+        let result = tokenize("<<citation>>");
+        let expected = vec![
+            Token::Tag(String::from("<<citation>")),
+            Token::Text(String::from(">")),
+        ];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn it_treats_arrows_and_operators_as_text() {
+        let result = tokenize_with_options("<p>a <- b <= c <!-- d --></p>", false, true);
+        let expected = vec![
+            Token::Tag(String::from("<p>")),
+            Token::Text(String::from("a <- b <= c ")),
+            Token::Tag(String::from("<!-- d -->")),
+            Token::Tag(String::from("</p>")),
+        ];
+        assert_eq!(result, expected);
+    }
 }
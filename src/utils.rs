@@ -1,5 +1,45 @@
 use fancy_regex::{Regex as FancyRegex};
+use regex::{Captures, Regex};
 
 pub fn create_re(s: &str) -> FancyRegex {
     FancyRegex::new(s).unwrap()
 }
+
+/// Replace the matches of `re` in `text` with the result of `replace`,
+/// which gets the text before and after the match as well as the match
+/// itself.
+///
+/// If `replace` returns `None`, the match is left alone, and we look for
+/// the next match from the following character -- the same as a failed
+/// lookbehind or lookahead would.  This lets us check the context of a
+/// match with a plain `Regex`, rather than using lookaround in a
+/// `FancyRegex`, which gives up on long texts.
+pub fn replace_all_with_context(
+    text: &str,
+    re: &Regex,
+    mut replace: impl FnMut(&Captures, &str, &str) -> Option<String>
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last_end = 0;
+    let mut pos = 0;
+
+    while let Some(caps) = re.captures_at(text, pos) {
+        let m = caps.get(0).unwrap();
+
+        match replace(&caps, &text[..m.start()], &text[m.end()..]) {
+            Some(replacement) if !m.is_empty() => {
+                result.push_str(&text[last_end..m.start()]);
+                result.push_str(&replacement);
+                last_end = m.end();
+                pos = m.end();
+            },
+            _ => match text[m.start()..].chars().next() {
+                Some(c) => pos = m.start() + c.len_utf8(),
+                None    => break,
+            },
+        }
+    }
+
+    result.push_str(&text[last_end..]);
+    result
+}
@@ -0,0 +1,37 @@
+// This file contains the warnings we can report about the input, e.g.
+// a quotation that's opened but never closed.
+//
+// We can't fix these for the author -- we don't know where the missing
+// quote should go -- but we can tell them where to look.
+
+use std::fmt;
+
+/// Something in the input that looks like a mistake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+
+    /// The byte offset in the input of the thing we're warning about.
+    pub offset: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarningKind {
+    /// A quote opens a quotation, but it isn't closed before the end of
+    /// the block element it's in
+    UnclosedQuote,
+
+    /// A quote closes a quotation, but there's no quotation to close
+    UnopenedQuote,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self.kind {
+            WarningKind::UnclosedQuote => "quotation is never closed",
+            WarningKind::UnopenedQuote => "quotation is closed but never opened",
+        };
+
+        write!(f, "{} (at byte {})", description, self.offset)
+    }
+}